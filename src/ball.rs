@@ -1,15 +1,114 @@
+use crate::motion::*;
 use nannou::color::Hsv;
 use nannou::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+// Layer bits an object belongs to and the layers it collides with. Two objects interact
+// only when each one's mask accepts a layer of the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionFilter {
+    pub layers: u32,
+    pub mask: u32,
+}
+
+impl CollisionFilter {
+    pub fn new(layers: u32, mask: u32) -> Self {
+        CollisionFilter { layers, mask }
+    }
+
+    pub fn interacts(&self, other: &CollisionFilter) -> bool {
+        (self.layers & other.mask) != 0 && (other.layers & self.mask) != 0
+    }
+}
+
+impl Default for CollisionFilter {
+    fn default() -> Self {
+        CollisionFilter {
+            layers: 1,
+            mask: u32::MAX,
+        }
+    }
+}
+
+// Clones keep the id, so only one copy should be in the solver at a time
+#[derive(Clone)]
 pub struct Ball {
     pub prev_pos: Vec2,
     pub pos: Vec2,
     pub radius: f32,
     pub acc: Vec2,
     pub color: Hsv,
+    pub filter: CollisionFilter,
+    // Order in which the ball was spawned, indexing `Solver::colormap`
+    pub spawn_index: usize,
+    // Bit i is set while the ball is inside `Solver::sensors[i]`
+    pub sensors: u64,
+    pub motion: Motion,
+    // Unique for the life of the program, unlike the index into `Solver::balls` which
+    // shifts as balls are removed
+    pub id: u64,
 }
 
+// `spawn_index` of balls added by hand, which have no entry in the colormap
+pub const PAINTED: usize = usize::MAX;
+// `id` of probe balls, which only test shapes and never join the solver
+pub const PROBE: u64 = u64::MAX;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
 impl Ball {
+    pub fn new(pos: Vec2, prev_pos: Vec2, radius: f32, color: Hsv) -> Self {
+        Ball {
+            prev_pos,
+            color,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            ..Ball::probe(pos, radius)
+        }
+    }
+
+    // A white ball at rest that doesn't use up an id, for asking shapes about a point
+    pub fn probe(pos: Vec2, radius: f32) -> Self {
+        Ball {
+            prev_pos: pos,
+            pos,
+            radius,
+            acc: Vec2::ZERO,
+            color: Hsv::new(0., 0., 1.),
+            filter: CollisionFilter::default(),
+            spawn_index: 0,
+            sensors: 0,
+            motion: Motion::Dynamic,
+            id: PROBE,
+        }
+    }
+
+    // Line for a scene file, see `parse_ball`. Balls are saved at rest.
+    pub fn describe(&self) -> String {
+        let mut line = format!(
+            "ball pos={},{} radius={} color={},{},{} layers={:x} mask={:x}",
+            self.pos.x,
+            self.pos.y,
+            self.radius,
+            self.color.hue.to_positive_degrees(),
+            self.color.saturation,
+            self.color.value,
+            self.filter.layers,
+            self.filter.mask
+        );
+        if self.motion != Motion::Dynamic {
+            line += &format!(" motion={}", self.motion.describe());
+        }
+        line
+    }
+
+    // Share of a position correction the ball takes, none unless it is dynamic
+    pub fn mobility(&self) -> f32 {
+        match self.motion {
+            Motion::Dynamic => 1.,
+            _ => 0.,
+        }
+    }
+
     pub fn detect_pair_collide(a: &Ball, b: &Ball) -> bool {
         let sum_radii_sqr = (a.radius + b.radius).pow(2);
         let dpos = a.pos - b.pos;
@@ -17,15 +116,24 @@ impl Ball {
     }
 
     pub fn resolve_pair_collide(a: &mut Ball, b: &mut Ball) {
+        let (mobility_a, mobility_b) = (a.mobility(), b.mobility());
+        if mobility_a + mobility_b == 0. {
+            return;
+        }
+        let share = mobility_a / (mobility_a + mobility_b);
         let axis = (a.pos - b.pos).normalize();
         let overlap = (a.radius + b.radius) - (a.pos - b.pos).length();
-        a.pos = a.pos + axis * (0.5 * overlap);
-        b.pos = b.pos - axis * (0.5 * overlap);
+        a.pos = a.pos + axis * (share * overlap);
+        b.pos = b.pos - axis * ((1. - share) * overlap);
     }
     pub fn update(&mut self, dt: f32) {
+        if self.motion != Motion::Dynamic {
+            // Kinematic balls are then moved on by `drive`
+            self.prev_pos = self.pos;
+            self.acc = Vec2::ZERO;
+            return;
+        }
         let vel = self.pos - self.prev_pos;
-        // self.color = Hsv::new(0.1 * vel.length_squared() / 1. * 480., 1., 1.);
-        // self.color = Hsv::new(0.05 * vel.length_squared() / 1. * 480., 1., 1.);
         // self.radius = 10. * (vel.length_squared() / 2.);
         // self.radius = self.radius.clamp(2., 4.);
         self.prev_pos = self.pos;
@@ -33,7 +141,19 @@ impl Ball {
         self.acc = Vec2::ZERO;
     }
 
+    // Puts a kinematic ball where its path is at `time`
+    pub fn drive(&mut self, time: f32) {
+        if let Motion::Kinematic(path) = &self.motion {
+            self.pos = path.at(time);
+        }
+    }
+
     pub fn accelerate(&mut self, acc: Vec2) {
         self.acc += acc;
     }
+
+    // Instantly changes the velocity, measured as displacement per step
+    pub fn impulse(&mut self, dv: Vec2) {
+        self.prev_pos -= dv;
+    }
 }
@@ -7,6 +7,37 @@ pub enum BoundaryType {
     Inner,
     Outer,
 }
+impl BoundaryType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoundaryType::Inner => "inner",
+            BoundaryType::Outer => "outer",
+        }
+    }
+}
+
+// The `describe` fields every shape shares
+pub fn describe_common(kind: BoundaryType, sink: bool, filter: CollisionFilter) -> String {
+    format!(
+        "kind={} sink={} layers={:x} mask={:x}",
+        kind.name(),
+        sink,
+        filter.layers,
+        filter.mask
+    )
+}
+
+// Closed loop of points around an ellipse, rotated by `angle`
+pub fn ellipse_points(pos: Vec2, half_size: Vec2, angle: f32) -> Vec<Vec2> {
+    let resolution = 64;
+    (0..=resolution)
+        .map(|i| {
+            let theta = 2. * PI * i as f32 / resolution as f32;
+            pos + Vec2::new(half_size.x * theta.cos(), half_size.y * theta.sin()).rotate(angle)
+        })
+        .collect()
+}
+
 pub trait Boundary {
     fn kind(&self) -> BoundaryType;
     fn apply_outer_constraint(&self, ball: &mut Ball);
@@ -14,8 +45,19 @@ pub trait Boundary {
     fn detect_inner_collision(&self, ball: &Ball) -> bool;
     fn detect_outer_collision(&self, ball: &Ball) -> bool;
     fn set_pos(&mut self, new_pos: Vec2);
+    fn pos(&self) -> Vec2;
+    fn set_kind(&mut self, kind: BoundaryType);
+    fn set_sink(&mut self, sink: bool);
+    // Points the editor lets users drag to resize or reshape the shape
+    fn handles(&self) -> Vec<Vec2>;
+    fn set_handle(&mut self, index: usize, pos: Vec2);
     fn draw(&self, draw: &Draw);
+    // Closed polylines tracing the shape's walls, for exporters
+    fn outline(&self) -> Vec<Vec<Vec2>>;
+    // One line of `name key=value ...` pairs that fully describes the shape
+    fn describe(&self) -> String;
     fn sink(&self) -> bool;
+    fn filter(&self) -> CollisionFilter;
 }
 
 pub struct RectBound {
@@ -24,18 +66,40 @@ pub struct RectBound {
     pub width: f32,
     pub height: f32,
     pub sink: bool,
+    pub filter: CollisionFilter,
 }
 
 impl Boundary for RectBound {
     fn sink(&self) -> bool {
         self.sink
     }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
     fn kind(&self) -> BoundaryType {
         return self.kind;
     }
     fn set_pos(&mut self, new_pos: Vec2) {
         self.pos = new_pos;
     }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    // A corner, resizing symmetrically about the centre
+    fn handles(&self) -> Vec<Vec2> {
+        vec![self.pos + Vec2::new(self.width, self.height) / 2.]
+    }
+    fn set_handle(&mut self, _index: usize, pos: Vec2) {
+        let half = (pos - self.pos).abs();
+        self.width = 2. * half.x.max(1.);
+        self.height = 2. * half.y.max(1.);
+    }
     fn apply_inner_constraint(&self, ball: &mut Ball) {
         let half_width = self.width / 2.;
         let half_height = self.height / 2.;
@@ -112,6 +176,28 @@ impl Boundary for RectBound {
 
         (ball.pos.y > bot && ball.pos.y < top) && (ball.pos.x > left && ball.pos.x < right)
     }
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        let half = Vec2::new(self.width / 2., self.height / 2.);
+        vec![vec![
+            self.pos + Vec2::new(-half.x, -half.y),
+            self.pos + Vec2::new(half.x, -half.y),
+            self.pos + Vec2::new(half.x, half.y),
+            self.pos + Vec2::new(-half.x, half.y),
+            self.pos + Vec2::new(-half.x, -half.y),
+        ]]
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "rect pos={},{} width={} height={} {}",
+            self.pos.x,
+            self.pos.y,
+            self.width,
+            self.height,
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
     fn draw(&self, draw: &Draw) {
         draw.rect()
             .xy(self.pos)
@@ -128,18 +214,37 @@ pub struct CircleBound {
     pub radius: f32,
     pub kind: BoundaryType,
     pub sink: bool,
+    pub filter: CollisionFilter,
 }
 
 impl Boundary for CircleBound {
     fn sink(&self) -> bool {
         self.sink
     }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
     fn kind(&self) -> BoundaryType {
         return self.kind;
     }
     fn set_pos(&mut self, new_pos: Vec2) {
         self.pos = new_pos;
     }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    fn handles(&self) -> Vec<Vec2> {
+        vec![self.pos + Vec2::new(self.radius, 0.)]
+    }
+    fn set_handle(&mut self, _index: usize, pos: Vec2) {
+        self.radius = (pos - self.pos).length().max(1.);
+    }
     fn apply_inner_constraint(&self, ball: &mut Ball) {
         let normal = (ball.pos - self.pos).normalize();
         ball.pos = self.pos + normal * (self.radius - ball.radius);
@@ -155,6 +260,20 @@ impl Boundary for CircleBound {
         (ball.pos - self.pos).length_squared() < (self.radius + ball.radius).pow(2)
     }
 
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        vec![ellipse_points(self.pos, Vec2::splat(self.radius), 0.)]
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "circle pos={},{} radius={} {}",
+            self.pos.x,
+            self.pos.y,
+            self.radius,
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
     fn draw(&self, draw: &Draw) {
         draw.ellipse()
             .radius(self.radius)
@@ -164,3 +283,672 @@ impl Boundary for CircleBound {
             .xy(self.pos);
     }
 }
+
+pub struct CapsuleBound {
+    pub pos: Vec2,
+    pub length: f32,
+    pub angle: f32,
+    pub radius: f32,
+    pub kind: BoundaryType,
+    pub sink: bool,
+    pub filter: CollisionFilter,
+}
+
+impl CapsuleBound {
+    // Closest point to p on the capsule's core segment
+    fn closest_point(&self, p: Vec2) -> Vec2 {
+        let axis = Vec2::new(1., 0.).rotate(self.angle);
+        let half_length = self.length / 2.;
+        let t = (p - self.pos).dot(axis).clamp(-half_length, half_length);
+        self.pos + axis * t
+    }
+
+    // Direction from the core segment to p, falling back to the segment normal when p is on it
+    fn normal(&self, p: Vec2, closest: Vec2) -> Vec2 {
+        let d = p - closest;
+        if d.length_squared() > 0. {
+            d.normalize()
+        } else {
+            Vec2::new(0., 1.).rotate(self.angle)
+        }
+    }
+}
+
+impl Boundary for CapsuleBound {
+    fn sink(&self) -> bool {
+        self.sink
+    }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
+    fn kind(&self) -> BoundaryType {
+        self.kind
+    }
+    fn set_pos(&mut self, new_pos: Vec2) {
+        self.pos = new_pos;
+    }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    // One end of the core segment, then a point on the side setting the radius
+    fn handles(&self) -> Vec<Vec2> {
+        vec![
+            self.pos + Vec2::new(self.length / 2., 0.).rotate(self.angle),
+            self.pos + Vec2::new(0., self.radius).rotate(self.angle),
+        ]
+    }
+    fn set_handle(&mut self, index: usize, pos: Vec2) {
+        let offset = pos - self.pos;
+        if index == 0 {
+            self.length = 2. * offset.length();
+            self.angle = offset.y.atan2(offset.x);
+        } else {
+            self.radius = offset.length().max(1.);
+        }
+    }
+    fn apply_inner_constraint(&self, ball: &mut Ball) {
+        let closest = self.closest_point(ball.pos);
+        let normal = self.normal(ball.pos, closest);
+        ball.pos = closest + normal * (self.radius - ball.radius);
+    }
+    fn apply_outer_constraint(&self, ball: &mut Ball) {
+        let closest = self.closest_point(ball.pos);
+        let normal = self.normal(ball.pos, closest);
+        ball.pos = closest + normal * (self.radius + ball.radius);
+    }
+    fn detect_inner_collision(&self, ball: &Ball) -> bool {
+        (ball.pos - self.closest_point(ball.pos)).length_squared()
+            > (self.radius - ball.radius).pow(2)
+    }
+    fn detect_outer_collision(&self, ball: &Ball) -> bool {
+        (ball.pos - self.closest_point(ball.pos)).length_squared()
+            < (self.radius + ball.radius).pow(2)
+    }
+
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        // Two half circles joined at the ends of the core segment
+        let axis = Vec2::new(1., 0.).rotate(self.angle);
+        let half_length = self.length / 2.;
+        let resolution = 24;
+        let right = (0..=resolution).map(|i| {
+            let theta = self.angle - PI / 2. + PI * i as f32 / resolution as f32;
+            self.pos + axis * half_length + Vec2::new(self.radius, 0.).rotate(theta)
+        });
+        let left = (0..=resolution).map(|i| {
+            let theta = self.angle + PI / 2. + PI * i as f32 / resolution as f32;
+            self.pos - axis * half_length + Vec2::new(self.radius, 0.).rotate(theta)
+        });
+        let mut points: Vec<Vec2> = right.chain(left).collect();
+        points.push(points[0]);
+        vec![points]
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "capsule pos={},{} length={} angle={} radius={} {}",
+            self.pos.x,
+            self.pos.y,
+            self.length,
+            self.angle,
+            self.radius,
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
+    fn draw(&self, draw: &Draw) {
+        for points in self.outline() {
+            draw.polyline().stroke_weight(1.).color(WHITE).points(points);
+        }
+    }
+}
+
+pub struct EllipseBound {
+    pub pos: Vec2,
+    pub half_width: f32,
+    pub half_height: f32,
+    pub angle: f32,
+    pub kind: BoundaryType,
+    pub sink: bool,
+    pub filter: CollisionFilter,
+}
+
+impl EllipseBound {
+    fn to_local(&self, p: Vec2) -> Vec2 {
+        (p - self.pos).rotate(-self.angle)
+    }
+
+    fn inside(&self, p: Vec2) -> bool {
+        let local = self.to_local(p);
+        (local.x / self.half_width).powi(2) + (local.y / self.half_height).powi(2) < 1.
+    }
+
+    // Closest point on the ellipse and its outward normal, both in local coordinates.
+    // Iterates on the parametric angle, which converges in a handful of steps.
+    fn closest_local(&self, local: Vec2) -> (Vec2, Vec2) {
+        let (a, b) = (self.half_width, self.half_height);
+        let px = local.x.abs();
+        let py = local.y.abs();
+        let mut tx = std::f32::consts::FRAC_1_SQRT_2;
+        let mut ty = std::f32::consts::FRAC_1_SQRT_2;
+        for _ in 0..4 {
+            let x = a * tx;
+            let y = b * ty;
+            let ex = (a * a - b * b) * tx.powi(3) / a;
+            let ey = (b * b - a * a) * ty.powi(3) / b;
+            let r = Vec2::new(x - ex, y - ey);
+            let q = Vec2::new(px - ex, py - ey);
+            let scale = r.length() / q.length().max(f32::EPSILON);
+            let t = Vec2::new(
+                ((q.x * scale + ex) / a).clamp(0., 1.),
+                ((q.y * scale + ey) / b).clamp(0., 1.),
+            )
+            .normalize();
+            tx = t.x;
+            ty = t.y;
+        }
+        let closest = Vec2::new((a * tx).copysign(local.x), (b * ty).copysign(local.y));
+        let normal = Vec2::new(closest.x / (a * a), closest.y / (b * b)).normalize();
+        (closest, normal)
+    }
+
+    fn closest_point(&self, p: Vec2) -> (Vec2, Vec2) {
+        let (closest, normal) = self.closest_local(self.to_local(p));
+        (
+            self.pos + closest.rotate(self.angle),
+            normal.rotate(self.angle),
+        )
+    }
+}
+
+impl Boundary for EllipseBound {
+    fn sink(&self) -> bool {
+        self.sink
+    }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
+    fn kind(&self) -> BoundaryType {
+        self.kind
+    }
+    fn set_pos(&mut self, new_pos: Vec2) {
+        self.pos = new_pos;
+    }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    // Ends of the two half axes. The first also sets the rotation.
+    fn handles(&self) -> Vec<Vec2> {
+        vec![
+            self.pos + Vec2::new(self.half_width, 0.).rotate(self.angle),
+            self.pos + Vec2::new(0., self.half_height).rotate(self.angle),
+        ]
+    }
+    fn set_handle(&mut self, index: usize, pos: Vec2) {
+        let offset = pos - self.pos;
+        if index == 0 {
+            self.half_width = offset.length().max(1.);
+            self.angle = offset.y.atan2(offset.x);
+        } else {
+            self.half_height = offset.length().max(1.);
+        }
+    }
+    fn apply_inner_constraint(&self, ball: &mut Ball) {
+        let (closest, normal) = self.closest_point(ball.pos);
+        ball.pos = closest - normal * ball.radius;
+    }
+    fn apply_outer_constraint(&self, ball: &mut Ball) {
+        let (closest, normal) = self.closest_point(ball.pos);
+        ball.pos = closest + normal * ball.radius;
+    }
+    fn detect_inner_collision(&self, ball: &Ball) -> bool {
+        let (closest, _) = self.closest_point(ball.pos);
+        !self.inside(ball.pos) || (ball.pos - closest).length_squared() < ball.radius.pow(2)
+    }
+    fn detect_outer_collision(&self, ball: &Ball) -> bool {
+        let (closest, _) = self.closest_point(ball.pos);
+        self.inside(ball.pos) || (ball.pos - closest).length_squared() < ball.radius.pow(2)
+    }
+
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        vec![ellipse_points(
+            self.pos,
+            Vec2::new(self.half_width, self.half_height),
+            self.angle,
+        )]
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "ellipse pos={},{} half_width={} half_height={} angle={} {}",
+            self.pos.x,
+            self.pos.y,
+            self.half_width,
+            self.half_height,
+            self.angle,
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
+    fn draw(&self, draw: &Draw) {
+        draw.ellipse()
+            .w_h(2. * self.half_width, 2. * self.half_height)
+            .rotate(self.angle)
+            .no_fill()
+            .stroke_weight(1.)
+            .stroke(WHITE)
+            .xy(self.pos);
+    }
+}
+
+pub struct AnnulusBound {
+    pub pos: Vec2,
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+    pub kind: BoundaryType,
+    pub sink: bool,
+    pub filter: CollisionFilter,
+}
+
+impl Boundary for AnnulusBound {
+    fn sink(&self) -> bool {
+        self.sink
+    }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
+    fn kind(&self) -> BoundaryType {
+        self.kind
+    }
+    fn set_pos(&mut self, new_pos: Vec2) {
+        self.pos = new_pos;
+    }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    fn handles(&self) -> Vec<Vec2> {
+        vec![
+            self.pos + Vec2::new(self.inner_radius, 0.),
+            self.pos + Vec2::new(self.outer_radius, 0.),
+        ]
+    }
+    fn set_handle(&mut self, index: usize, pos: Vec2) {
+        let radius = (pos - self.pos).length();
+        if index == 0 {
+            self.inner_radius = radius.min(self.outer_radius - 1.).max(0.);
+        } else {
+            self.outer_radius = radius.max(self.inner_radius + 1.);
+        }
+    }
+    // Inner keeps balls inside the ring channel between the two walls
+    fn apply_inner_constraint(&self, ball: &mut Ball) {
+        let offset = ball.pos - self.pos;
+        // A ball exactly on the centre has no direction of its own, so it leaves along x
+        let normal = offset.try_normalize().unwrap_or(Vec2::X);
+        let dist = offset.length().clamp(
+            self.inner_radius + ball.radius,
+            self.outer_radius - ball.radius,
+        );
+        ball.pos = self.pos + normal * dist;
+    }
+    // Outer treats the ring itself as solid, pushing balls to whichever side is closer
+    fn apply_outer_constraint(&self, ball: &mut Ball) {
+        let offset = ball.pos - self.pos;
+        let normal = offset.try_normalize().unwrap_or(Vec2::X);
+        let dist = offset.length();
+        let hole = self.inner_radius - ball.radius;
+        let outside = self.outer_radius + ball.radius;
+        if dist - hole < outside - dist {
+            ball.pos = self.pos + normal * hole.max(0.);
+        } else {
+            ball.pos = self.pos + normal * outside;
+        }
+    }
+    fn detect_inner_collision(&self, ball: &Ball) -> bool {
+        let dist_sqr = (ball.pos - self.pos).length_squared();
+        dist_sqr < (self.inner_radius + ball.radius).pow(2)
+            || dist_sqr > (self.outer_radius - ball.radius).pow(2)
+    }
+    fn detect_outer_collision(&self, ball: &Ball) -> bool {
+        let dist_sqr = (ball.pos - self.pos).length_squared();
+        let hole = (self.inner_radius - ball.radius).max(0.);
+        dist_sqr > hole.pow(2) && dist_sqr < (self.outer_radius + ball.radius).pow(2)
+    }
+
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        vec![
+            ellipse_points(self.pos, Vec2::splat(self.inner_radius), 0.),
+            ellipse_points(self.pos, Vec2::splat(self.outer_radius), 0.),
+        ]
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "annulus pos={},{} inner_radius={} outer_radius={} {}",
+            self.pos.x,
+            self.pos.y,
+            self.inner_radius,
+            self.outer_radius,
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
+    fn draw(&self, draw: &Draw) {
+        for radius in [self.inner_radius, self.outer_radius] {
+            draw.ellipse()
+                .radius(radius)
+                .no_fill()
+                .stroke_weight(1.)
+                .stroke(WHITE)
+                .xy(self.pos);
+        }
+    }
+}
+
+// Any simple polygon, with vertices stored relative to `pos` so moving it is cheap
+pub struct PolygonBound {
+    pub pos: Vec2,
+    pub points: Vec<Vec2>,
+    pub kind: BoundaryType,
+    pub sink: bool,
+    pub filter: CollisionFilter,
+}
+
+impl PolygonBound {
+    // Centres the vertices on their centroid
+    pub fn from_world(points: &[Vec2], kind: BoundaryType) -> Self {
+        let pos = points.iter().fold(Vec2::ZERO, |a, b| a + *b) / points.len().max(1) as f32;
+        PolygonBound {
+            pos,
+            points: points.iter().map(|p| *p - pos).collect(),
+            kind,
+            sink: false,
+            filter: CollisionFilter::default(),
+        }
+    }
+
+    fn world_points(&self) -> Vec<Vec2> {
+        self.points.iter().map(|p| self.pos + *p).collect()
+    }
+
+    // Even-odd rule
+    fn inside(&self, p: Vec2) -> bool {
+        let local = p - self.pos;
+        let mut inside = false;
+        for (i, a) in self.points.iter().enumerate() {
+            let b = self.points[(i + 1) % self.points.len()];
+            if (a.y > local.y) != (b.y > local.y)
+                && local.x < a.x + (local.y - a.y) * (b.x - a.x) / (b.y - a.y)
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    // Closest point on the walls and the normal pointing out of the polygon there
+    fn closest_point(&self, p: Vec2) -> (Vec2, Vec2) {
+        let local = p - self.pos;
+        let mut best = (f32::MAX, Vec2::ZERO, Vec2::ZERO);
+        for (i, a) in self.points.iter().enumerate() {
+            let b = self.points[(i + 1) % self.points.len()];
+            let edge = b - *a;
+            let t =
+                ((local - *a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0., 1.);
+            let closest = *a + edge * t;
+            let dist = (local - closest).length_squared();
+            if dist < best.0 {
+                best = (dist, closest, edge.perp());
+            }
+        }
+        let (_, closest, edge_normal) = best;
+        let inside = self.inside(p);
+        let d = local - closest;
+        let normal = if d.length_squared() > 0. {
+            if inside {
+                -d
+            } else {
+                d
+            }
+        } else {
+            // On the wall itself, orient the edge normal using the polygon's winding
+            let area: f32 = (0..self.points.len())
+                .map(|i| self.points[i].perp_dot(self.points[(i + 1) % self.points.len()]))
+                .sum();
+            if area > 0. {
+                -edge_normal
+            } else {
+                edge_normal
+            }
+        };
+        (self.pos + closest, normal.normalize_or_zero())
+    }
+}
+
+impl Boundary for PolygonBound {
+    fn sink(&self) -> bool {
+        self.sink
+    }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
+    fn kind(&self) -> BoundaryType {
+        self.kind
+    }
+    fn set_pos(&mut self, new_pos: Vec2) {
+        self.pos = new_pos;
+    }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    fn handles(&self) -> Vec<Vec2> {
+        self.world_points()
+    }
+    fn set_handle(&mut self, index: usize, pos: Vec2) {
+        if let Some(point) = self.points.get_mut(index) {
+            *point = pos - self.pos;
+        }
+    }
+    fn apply_inner_constraint(&self, ball: &mut Ball) {
+        let (closest, normal) = self.closest_point(ball.pos);
+        ball.pos = closest - normal * ball.radius;
+    }
+    fn apply_outer_constraint(&self, ball: &mut Ball) {
+        let (closest, normal) = self.closest_point(ball.pos);
+        ball.pos = closest + normal * ball.radius;
+    }
+    fn detect_inner_collision(&self, ball: &Ball) -> bool {
+        let (closest, _) = self.closest_point(ball.pos);
+        !self.inside(ball.pos) || (ball.pos - closest).length_squared() < ball.radius.pow(2)
+    }
+    fn detect_outer_collision(&self, ball: &Ball) -> bool {
+        let (closest, _) = self.closest_point(ball.pos);
+        self.inside(ball.pos) || (ball.pos - closest).length_squared() < ball.radius.pow(2)
+    }
+
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        let mut points = self.world_points();
+        if let Some(first) = points.first() {
+            points.push(*first);
+        }
+        vec![points]
+    }
+
+    fn describe(&self) -> String {
+        let points: Vec<String> = self
+            .points
+            .iter()
+            .map(|p| format!("{},{}", p.x, p.y))
+            .collect();
+        format!(
+            "polygon pos={},{} points={} {}",
+            self.pos.x,
+            self.pos.y,
+            points.join(";"),
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
+    fn draw(&self, draw: &Draw) {
+        for points in self.outline() {
+            draw.polyline()
+                .stroke_weight(1.)
+                .color(WHITE)
+                .points(points);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annulus() -> AnnulusBound {
+        AnnulusBound {
+            pos: Vec2::new(10., -5.),
+            inner_radius: 20.,
+            outer_radius: 60.,
+            kind: BoundaryType::Inner,
+            sink: false,
+            filter: CollisionFilter::default(),
+        }
+    }
+
+    #[test]
+    fn annulus_pushes_a_centred_ball_into_the_channel() {
+        let bound = annulus();
+        let mut ball = Ball::new(bound.pos, bound.pos, 5., Hsv::new(0., 0., 1.));
+        assert!(bound.detect_inner_collision(&ball));
+        bound.apply_inner_constraint(&mut ball);
+        assert!((ball.pos.distance(bound.pos) - 25.).abs() < 1e-4);
+        assert!(!bound.detect_inner_collision(&ball));
+    }
+
+    #[test]
+    fn annulus_keeps_a_ball_in_the_channel_where_it_is() {
+        let bound = annulus();
+        let start = bound.pos + Vec2::new(0., 70.);
+        let mut ball = Ball::new(start, start, 5., Hsv::new(0., 0., 1.));
+        bound.apply_inner_constraint(&mut ball);
+        assert!(ball.pos.abs_diff_eq(bound.pos + Vec2::new(0., 55.), 1e-4));
+    }
+
+    fn capsule(angle: f32) -> CapsuleBound {
+        CapsuleBound {
+            pos: Vec2::new(10., -5.),
+            length: 40.,
+            angle,
+            radius: 8.,
+            kind: BoundaryType::Outer,
+            sink: false,
+            filter: CollisionFilter::default(),
+        }
+    }
+
+    #[test]
+    fn capsule_projects_onto_its_core_segment() {
+        for angle in [0., PI / 3.] {
+            let bound = capsule(angle);
+            let along = |t: f32, side: f32| bound.pos + Vec2::new(t, side).rotate(angle);
+            // Beside the middle of the segment, straight across
+            let closest = bound.closest_point(along(5., 30.));
+            assert!(closest.abs_diff_eq(along(5., 0.), 1e-4), "{}", angle);
+            // Past either end, the end itself
+            let closest = bound.closest_point(along(35., -12.));
+            assert!(closest.abs_diff_eq(along(20., 0.), 1e-4), "{}", angle);
+            let closest = bound.closest_point(along(-50., 3.));
+            assert!(closest.abs_diff_eq(along(-20., 0.), 1e-4), "{}", angle);
+        }
+    }
+
+    #[test]
+    fn capsule_normal_falls_back_to_the_side_on_the_segment() {
+        let bound = capsule(PI / 2.);
+        let on_segment = bound.pos + Vec2::new(0., 10.);
+        let normal = bound.normal(on_segment, bound.closest_point(on_segment));
+        assert!(normal.abs_diff_eq(Vec2::new(-1., 0.), 1e-4));
+    }
+
+    fn ellipse(half_width: f32, half_height: f32) -> EllipseBound {
+        EllipseBound {
+            pos: Vec2::new(10., -5.),
+            half_width,
+            half_height,
+            angle: 0.,
+            kind: BoundaryType::Outer,
+            sink: false,
+            filter: CollisionFilter::default(),
+        }
+    }
+
+    fn on_ellipse(bound: &EllipseBound, p: Vec2) -> bool {
+        let local = p - bound.pos;
+        let value = (local.x / bound.half_width).powi(2) + (local.y / bound.half_height).powi(2);
+        (value - 1.).abs() < 1e-3
+    }
+
+    #[test]
+    fn ellipse_projects_points_on_its_axes_to_the_vertices() {
+        let bound = ellipse(50., 20.);
+        for (offset, vertex, normal) in [
+            (Vec2::new(100., 0.), Vec2::new(50., 0.), Vec2::X),
+            // Inside, only points past (a^2 - b^2) / a = 42 from the centre land on a vertex
+            (Vec2::new(-45., 0.), Vec2::new(-50., 0.), -Vec2::X),
+            (Vec2::new(0., 80.), Vec2::new(0., 20.), Vec2::Y),
+            (Vec2::new(0., -5.), Vec2::new(0., -20.), -Vec2::Y),
+        ] {
+            let (closest, n) = bound.closest_point(bound.pos + offset);
+            assert!(closest.abs_diff_eq(bound.pos + vertex, 1e-3), "{}", offset);
+            assert!(n.abs_diff_eq(normal, 1e-3), "{}", offset);
+        }
+    }
+
+    #[test]
+    fn ellipse_projection_handles_near_degenerate_axes() {
+        for bound in [ellipse(50., 0.01), ellipse(0.01, 50.)] {
+            let p = bound.pos + Vec2::new(10., 5.);
+            let (closest, normal) = bound.closest_point(p);
+            assert!(closest.is_finite() && normal.is_finite());
+            assert!(on_ellipse(&bound, closest));
+            // Nothing on the ellipse is much closer than the flattened axis
+            let flat = if bound.half_width > bound.half_height {
+                Vec2::new(10., 0.)
+            } else {
+                Vec2::new(0., 5.)
+            };
+            assert!(closest.distance(p) <= (bound.pos + flat).distance(p) + 0.1);
+        }
+    }
+
+    #[test]
+    fn ellipse_projects_its_centre_onto_the_minor_axis() {
+        let bound = ellipse(50., 20.);
+        let (closest, normal) = bound.closest_point(bound.pos);
+        assert!(on_ellipse(&bound, closest));
+        assert!((closest.distance(bound.pos) - 20.).abs() < 1e-3);
+        assert!(normal.is_finite());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::ball::*;
+use crate::motion::*;
+use crate::palette::*;
+use crate::solver::*;
+use nannou::prelude::*;
+use random::Source;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Candidate positions tried per frame while painting
+const SPAWN_ATTEMPTS: usize = 8;
+// Fraction of the distance to the target a grabbed ball covers per substep
+const GRAB_STIFFNESS: f32 = 0.1;
+const GRAB_DAMPING: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushTool {
+    Spawn,
+    Erase,
+    Push,
+    Grab,
+    Peg,
+}
+
+impl BrushTool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrushTool::Spawn => "spawn",
+            BrushTool::Erase => "erase",
+            BrushTool::Push => "push",
+            BrushTool::Grab => "grab",
+            BrushTool::Peg => "peg",
+        }
+    }
+}
+
+// Mouse tools acting on the balls, applied once per frame while the button is held
+pub struct Brush {
+    pub tool: Option<BrushTool>,
+    // World radius of the brush
+    pub radius: f32,
+    pub ball_radius: f32,
+    pub color: Rgba,
+    color_index: usize,
+    // Push speed at the brush centre, in world units per substep
+    pub strength: f32,
+    pub active: bool,
+    // Ids of the grabbed balls and their offsets from the mouse. Ids stay valid while
+    // other balls are removed, and a grabbed ball that is removed is simply let go.
+    grabbed: HashMap<u64, Vec2>,
+    source: random::Default,
+}
+
+impl Brush {
+    pub fn new(ball_radius: f32) -> Self {
+        Brush {
+            tool: None,
+            radius: 40.,
+            ball_radius,
+            color: Palette::rainbow().sample(0.),
+            color_index: 0,
+            strength: 0.5,
+            active: false,
+            grabbed: HashMap::new(),
+            source: random::default(7),
+        }
+    }
+
+    // Off, then each tool in turn
+    pub fn next_tool(&mut self) {
+        self.tool = match self.tool {
+            None => Some(BrushTool::Spawn),
+            Some(BrushTool::Spawn) => Some(BrushTool::Erase),
+            Some(BrushTool::Erase) => Some(BrushTool::Push),
+            Some(BrushTool::Push) => Some(BrushTool::Grab),
+            Some(BrushTool::Grab) => Some(BrushTool::Peg),
+            Some(BrushTool::Peg) => None,
+        };
+        self.release();
+    }
+
+    pub fn describe(&self) -> String {
+        match self.tool {
+            Some(tool) => format!(
+                "brush: {} radius {:.0} ball {:.1} (Z tool, [ ] size, - = ball, P colour)",
+                tool.name(),
+                self.radius,
+                self.ball_radius
+            ),
+            None => String::new(),
+        }
+    }
+
+    pub fn press(&mut self, solver: &Solver, world: Vec2) {
+        self.active = true;
+        if self.tool == Some(BrushTool::Grab) {
+            self.grabbed = solver
+                .balls
+                .iter()
+                .filter_map(|ball| {
+                    let ball = ball.borrow();
+                    let offset = ball.pos - world;
+                    (offset.length() < self.radius).then_some((ball.id, offset))
+                })
+                .collect();
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.active = false;
+        self.grabbed.clear();
+    }
+
+    // Call before `Solver::update`. `dt` is the frame timestep, which sets how
+    // accelerations turn into displacements.
+    pub fn apply(&mut self, solver: &mut Solver, world: Vec2, dt: f32) {
+        let tool = match (self.tool, self.active) {
+            (Some(tool), true) => tool,
+            _ => return,
+        };
+        // Accelerations only act on the first substep, so express them per substep
+        let subdt = dt / solver.substeps as f32;
+        let per_substep = 1. / (subdt * subdt);
+        match tool {
+            BrushTool::Spawn => self.spawn(solver, world, Motion::Dynamic),
+            BrushTool::Peg => self.spawn(solver, world, Motion::Fixed),
+            BrushTool::Erase => {
+                let radius = self.radius;
+                solver.remove_balls(|ball| (ball.pos - world).length() < radius);
+            }
+            BrushTool::Push => {
+                for ball in solver.balls.iter() {
+                    let mut ball = ball.borrow_mut();
+                    let offset = ball.pos - world;
+                    let dist = offset.length();
+                    if dist < self.radius {
+                        // Strongest at the centre, fading to nothing at the edge
+                        let falloff = 1. - dist / self.radius;
+                        let dir = offset.try_normalize().unwrap_or(Vec2::Y);
+                        ball.accelerate(dir * self.strength * falloff * per_substep);
+                    }
+                }
+            }
+            BrushTool::Grab => {
+                // A damped spring pulling each ball towards its place under the mouse
+                for ball in solver.balls.iter() {
+                    let mut ball = ball.borrow_mut();
+                    if let Some(offset) = self.grabbed.get(&ball.id) {
+                        let stretch = world + *offset - ball.pos;
+                        let vel = ball.pos - ball.prev_pos;
+                        let acc = GRAB_STIFFNESS * stretch - GRAB_DAMPING * vel;
+                        ball.accelerate(acc * per_substep);
+                    }
+                }
+            }
+        }
+    }
+
+    // Adds balls at random points under the brush that don't overlap existing ones
+    fn spawn(&mut self, solver: &mut Solver, world: Vec2, motion: Motion) {
+        for _ in 0..SPAWN_ATTEMPTS {
+            let angle = self.source.read_f64() as f32 * 2. * PI;
+            // Square root keeps the points uniform over the disc
+            let dist = (self.source.read_f64() as f32).sqrt() * self.radius;
+            let pos = world + Vec2::new(dist, 0.).rotate(angle);
+            let free = solver.balls.iter().all(|ball| {
+                let ball = ball.borrow();
+                (ball.pos - pos).length() >= ball.radius + self.ball_radius
+            });
+            if free {
+                let mut ball = Ball::new(pos, pos, self.ball_radius, Hsv::from(self.color));
+                ball.spawn_index = PAINTED;
+                ball.motion = motion.clone();
+                // Pegs are part of the world every run, painted balls only this one
+                if motion == Motion::Dynamic {
+                    solver.balls.push(RefCell::new(ball));
+                } else {
+                    solver.add_fixture(ball);
+                }
+            }
+        }
+    }
+
+    pub fn next_color(&mut self) {
+        let colors = Palette::rainbow().colors();
+        self.color_index = (self.color_index + 1) % colors.len();
+        self.color = colors[self.color_index];
+    }
+
+    // Brush outline in world coordinates
+    pub fn draw(&self, draw: &Draw, world: Vec2, zoom: f32) {
+        if self.tool.is_none() {
+            return;
+        }
+        let color = if matches!(self.tool, Some(BrushTool::Spawn | BrushTool::Peg)) {
+            self.color
+        } else {
+            Rgba::new(1., 1., 1., 1.)
+        };
+        draw.ellipse()
+            .xy(world)
+            .radius(self.radius)
+            .no_fill()
+            .stroke_weight(1. / zoom)
+            .stroke(color);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::solver::*;
+use nannou::prelude::*;
+use std::collections::HashSet;
+
+// Maps world coordinates to the window: screen = fit_scale * zoom * (world - centre)
+pub struct Camera {
+    pub center: Vec2,
+    pub zoom: f32,
+    // Window pixels per world unit at zoom 1, so the world fills the window at any size
+    pub fit_scale: f32,
+    // Ids of the balls whose centroid the camera tracks
+    pub follow: HashSet<u64>,
+    // Screen position the current pan drag started from
+    drag_from: Option<Vec2>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            center: Vec2::ZERO,
+            zoom: 1.,
+            fit_scale: 1.,
+            follow: HashSet::new(),
+            drag_from: None,
+        }
+    }
+}
+
+impl Camera {
+    // Window pixels per world unit
+    pub fn scale(&self) -> f32 {
+        self.fit_scale * self.zoom
+    }
+
+    // Call when the window changes size. Only the view changes, not the world.
+    pub fn resize(&mut self, window: Rect, world: Rect) {
+        self.fit_scale = (window.w() / world.w()).min(window.h() / world.h());
+    }
+
+    // Draw context in world coordinates
+    pub fn transform(&self, draw: &Draw) -> Draw {
+        draw.scale(self.scale())
+            .translate((-self.center).extend(0.))
+    }
+
+    pub fn to_world(&self, screen: Vec2) -> Vec2 {
+        screen / self.scale() + self.center
+    }
+
+    // Zooms by `factor` keeping the world point under `screen` fixed
+    pub fn zoom_at(&mut self, screen: Vec2, factor: f32) {
+        let anchor = self.to_world(screen);
+        self.zoom = (self.zoom * factor).clamp(0.05, 50.);
+        self.center = anchor - screen / self.scale();
+    }
+
+    pub fn start_drag(&mut self, screen: Vec2) {
+        self.drag_from = Some(screen);
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag_from = None;
+    }
+
+    pub fn drag(&mut self, screen: Vec2) {
+        if let Some(from) = self.drag_from {
+            self.center -= (screen - from) / self.scale();
+            self.drag_from = Some(screen);
+        }
+    }
+
+    // Frames every ball and boundary inside the window with a small margin
+    pub fn fit(&mut self, solver: &Solver, window: Rect) {
+        let points = solver
+            .balls
+            .iter()
+            .flat_map(|b| {
+                let b = b.borrow();
+                [b.pos - Vec2::splat(b.radius), b.pos + Vec2::splat(b.radius)]
+            })
+            .chain(solver.boundaries.iter().flat_map(|b| b.outline().concat()));
+        let mut bounds: Option<Rect> = None;
+        for p in points {
+            let point = Rect::from_xy_wh(p, Vec2::ZERO);
+            bounds = Some(bounds.map_or(point, |b| b.stretch_to_point(p.to_array())));
+        }
+        if let Some(bounds) = bounds {
+            self.center = bounds.xy();
+            let scale =
+                0.95 * (window.w() / bounds.w().max(1.)).min(window.h() / bounds.h().max(1.));
+            self.zoom = scale / self.fit_scale;
+        }
+    }
+
+    // Tracks the balls within `radius` of `world`, or stops following if already tracking
+    pub fn toggle_follow(&mut self, solver: &Solver, world: Vec2, radius: f32) {
+        if !self.follow.is_empty() {
+            self.follow.clear();
+            return;
+        }
+        self.follow = solver
+            .balls
+            .iter()
+            .map(|b| b.borrow())
+            .filter(|b| (b.pos - world).length_squared() < radius * radius)
+            .map(|b| b.id)
+            .collect();
+    }
+
+    // Moves to the centroid of the followed balls that still exist
+    pub fn update(&mut self, solver: &Solver) {
+        if self.follow.is_empty() {
+            return;
+        }
+        let (sum, count) = solver
+            .balls
+            .iter()
+            .map(|b| b.borrow())
+            .filter(|b| self.follow.contains(&b.id))
+            .fold((Vec2::ZERO, 0), |(sum, count), b| (sum + b.pos, count + 1));
+        if count > 0 {
+            self.center = sum / count as f32;
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::partition::*;
+use crate::record::*;
+use crate::reveal::*;
+use nannou::prelude::*;
+
+pub const USAGE: &str =
+    "usage: fast_circles [--headless] [--frames N] [--svg PATH] [--crop X0,Y0,X1,Y1] \
+    [--gif PATH] [--y4m PATH|-] [--fps N] [--duration SECONDS] [--scale F] \
+    [--scene PATH] [--rate HZ] [--max-steps N] [--window WxH] [--periodic x|y|xy] [--mask PATH] \
+    [--image PATH] [--reveal cycle[:N]|sequential] [--palette NAME|PATH] \
+    [--quantize NAME|PATH] [--no-dither]";
+
+// Command line options. Without `--headless` the viewer opens as usual.
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    pub headless: bool,
+    // Frames to simulate before exporting in headless mode
+    pub frames: usize,
+    pub svg: Option<String>,
+    pub crop: Option<Rect>,
+    pub gif: Option<String>,
+    pub y4m: Option<String>,
+    pub fps: u32,
+    pub duration: Option<f32>,
+    // Output pixels per world unit for recordings
+    pub scale: f32,
+    // Scene file to load boundaries and settings from, and to save edits to
+    pub scene: Option<String>,
+    // Simulation steps per second of simulated time
+    pub rate: u32,
+    // Most simulation steps the viewer takes per rendered frame
+    pub max_steps: usize,
+    // Initial window size in pixels. The world keeps its size and is scaled to fit.
+    pub window: Option<(u32, u32)>,
+    // Axes along which the world wraps around instead of being walled in
+    pub periodic: Periodic,
+    // Image whose dark pixels are added as a solid boundary across the world
+    pub mask: Option<String>,
+    // Still image, animated GIF or directory of numbered frames for the balls to reveal,
+    // instead of the scene's
+    pub image: Option<String>,
+    pub reveal: Option<RevealMode>,
+    // Built-in palette name or palette file for the colour modes and spawners
+    pub palette: Option<String>,
+    // Palette the revealed colours are snapped to, and whether the error is spread
+    pub quantize: Option<String>,
+    pub no_dither: bool,
+}
+
+fn parse_floats(value: &str) -> Option<Vec<f32>> {
+    value.split(',').map(|v| v.trim().parse().ok()).collect()
+}
+
+impl CliArgs {
+    // Recordings show `world` unless cropped
+    pub fn record_options(&self, world: Rect) -> RecordOptions {
+        let mut options = RecordOptions {
+            fps: self.fps,
+            sim_rate: self.rate,
+            duration: self.duration,
+            ..RecordOptions::new(self.crop.unwrap_or(world))
+        };
+        options.raster.scale = self.scale;
+        options
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<CliArgs, String> {
+        let mut cli = CliArgs {
+            frames: 1000,
+            fps: 30,
+            scale: 1.,
+            rate: SIM_FPS,
+            max_steps: 8,
+            ..Default::default()
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--headless" => cli.headless = true,
+                "--frames" => {
+                    cli.frames = value()?
+                        .parse()
+                        .map_err(|_| "--frames needs a whole number".to_string())?
+                }
+                "--svg" => cli.svg = Some(value()?),
+                "--crop" => {
+                    let corners = parse_floats(&value()?)
+                        .filter(|v| v.len() == 4)
+                        .ok_or("--crop needs X0,Y0,X1,Y1".to_string())?;
+                    cli.crop = Some(Rect::from_corners(
+                        Vec2::new(corners[0], corners[1]),
+                        Vec2::new(corners[2], corners[3]),
+                    ));
+                }
+                "--gif" => cli.gif = Some(value()?),
+                "--y4m" => cli.y4m = Some(value()?),
+                "--fps" => {
+                    cli.fps = value()?
+                        .parse()
+                        .ok()
+                        .filter(|fps| *fps > 0)
+                        .ok_or("--fps needs a positive whole number".to_string())?
+                }
+                "--duration" => {
+                    cli.duration = Some(
+                        value()?
+                            .parse()
+                            .map_err(|_| "--duration needs a number of seconds".to_string())?,
+                    )
+                }
+                "--scale" => {
+                    cli.scale = value()?
+                        .parse()
+                        .ok()
+                        .filter(|scale| *scale > 0.)
+                        .ok_or("--scale needs a positive number".to_string())?
+                }
+                "--scene" => cli.scene = Some(value()?),
+                "--rate" => {
+                    cli.rate = value()?
+                        .parse()
+                        .ok()
+                        .filter(|rate| *rate > 0)
+                        .ok_or("--rate needs a positive whole number".to_string())?
+                }
+                "--max-steps" => {
+                    cli.max_steps = value()?
+                        .parse()
+                        .ok()
+                        .filter(|steps| *steps > 0)
+                        .ok_or("--max-steps needs a positive whole number".to_string())?
+                }
+                "--window" => {
+                    let size = value()?;
+                    cli.window = Some(
+                        size.split_once('x')
+                            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                            .filter(|(w, h)| *w > 0 && *h > 0)
+                            .ok_or("--window needs WIDTHxHEIGHT in pixels".to_string())?,
+                    );
+                }
+                "--periodic" => cli.periodic = Periodic::parse(&value()?)?,
+                "--mask" => cli.mask = Some(value()?),
+                "--image" => cli.image = Some(value()?),
+                "--reveal" => cli.reveal = Some(RevealMode::parse(&value()?)?),
+                "--palette" => cli.palette = Some(value()?),
+                "--quantize" => cli.quantize = Some(value()?),
+                "--no-dither" => cli.no_dither = true,
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<CliArgs, String> {
+        CliArgs::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn defaults() {
+        let cli = parse("").unwrap();
+        assert!(!cli.headless);
+        assert_eq!(cli.frames, 1000);
+        assert_eq!(cli.fps, 30);
+        assert_eq!(cli.rate, SIM_FPS);
+        assert_eq!(cli.max_steps, 8);
+        assert_eq!(cli.scale, 1.);
+        assert!(cli.svg.is_none() && cli.crop.is_none() && cli.window.is_none());
+        assert_eq!(cli.periodic, Periodic::default());
+    }
+
+    #[test]
+    fn headless_export() {
+        let cli = parse(
+            "--headless --frames 300 --svg out.svg --crop -100,-50,100,50 --y4m - --fps 25 \
+             --duration 2.5 --scale 2 --scene s.txt --rate 120 --window 640x480 --periodic xy \
+             --mask mask.png --image frames --reveal sequential --palette magma --quantize p.txt --no-dither",
+        )
+        .unwrap();
+        assert!(cli.headless);
+        assert_eq!(cli.frames, 300);
+        assert_eq!(cli.svg.as_deref(), Some("out.svg"));
+        let crop = cli.crop.unwrap();
+        assert_eq!((crop.left(), crop.bottom()), (-100., -50.));
+        assert_eq!((crop.right(), crop.top()), (100., 50.));
+        assert_eq!(cli.y4m.as_deref(), Some("-"));
+        assert_eq!(cli.fps, 25);
+        assert_eq!(cli.duration, Some(2.5));
+        assert_eq!(cli.scale, 2.);
+        assert_eq!(cli.scene.as_deref(), Some("s.txt"));
+        assert_eq!(cli.rate, 120);
+        assert_eq!(cli.window, Some((640, 480)));
+        assert_eq!(cli.periodic, Periodic { x: true, y: true });
+        assert_eq!(cli.mask.as_deref(), Some("mask.png"));
+        assert_eq!(cli.image.as_deref(), Some("frames"));
+        assert_eq!(cli.reveal, Some(RevealMode::Sequential));
+        assert_eq!(cli.palette.as_deref(), Some("magma"));
+        assert_eq!(cli.quantize.as_deref(), Some("p.txt"));
+        assert!(cli.no_dither);
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        for args in [
+            "--frames",
+            "--frames lots",
+            "--crop 1,2,3",
+            "--fps 0",
+            "--scale -1",
+            "--rate 0",
+            "--max-steps 0",
+            "--window 640",
+            "--window 0x480",
+            "--periodic z",
+            "--reveal cycle:0",
+            "--bogus",
+        ] {
+            assert!(parse(args).is_err(), "{}", args);
+        }
+    }
+
+    #[test]
+    fn record_options_default_to_the_world() {
+        let world = Rect::from_w_h(900., 880.);
+        let cli = parse("--fps 24 --rate 90 --scale 0.5").unwrap();
+        let options = cli.record_options(world);
+        assert_eq!((options.fps, options.sim_rate), (24, 90));
+        assert_eq!(options.raster.viewport, world);
+        assert_eq!(options.raster.scale, 0.5);
+    }
+}
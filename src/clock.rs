@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+// Longest real time one frame may account for, so a stall doesn't queue up a burst
+const MAX_FRAME_TIME: f32 = 0.25;
+
+// Fixed-rate simulation clock. Real time is accumulated and paid out as whole
+// simulation steps, so the simulation advances at the same rate whatever the
+// rendering rate is.
+pub struct SimClock {
+    // Simulation steps per second of simulated time
+    pub rate: u32,
+    // Most steps taken in one frame. Time beyond that is dropped, slowing the
+    // simulation rather than letting it fall further and further behind.
+    pub max_steps: usize,
+    // Simulated seconds per real second
+    pub speed: f32,
+    // Ignore real time and step for `fast_budget` each frame, so most frames go
+    // to simulation and few to rendering
+    pub fast: bool,
+    pub fast_budget: Duration,
+    // Steps taken since the clock started
+    pub steps: usize,
+    accumulator: f32,
+    last: Option<Instant>,
+}
+
+impl SimClock {
+    pub fn new(rate: u32, max_steps: usize) -> Self {
+        SimClock {
+            rate: rate.max(1),
+            max_steps: max_steps.max(1),
+            speed: 1.,
+            fast: false,
+            fast_budget: Duration::from_millis(250),
+            steps: 0,
+            accumulator: 0.,
+            last: None,
+        }
+    }
+
+    // Steps owed for the real time since the last call
+    pub fn advance(&mut self) -> usize {
+        let now = Instant::now();
+        let elapsed = self
+            .last
+            .map_or(0., |last| (now - last).as_secs_f32().min(MAX_FRAME_TIME));
+        self.last = Some(now);
+
+        let step_time = 1. / self.rate as f32;
+        self.accumulator += elapsed * self.speed;
+        let owed = (self.accumulator / step_time) as usize;
+        let steps = owed.min(self.max_steps);
+        self.accumulator -= steps as f32 * step_time;
+        if owed > steps {
+            self.accumulator = self.accumulator.min(step_time);
+        }
+        steps
+    }
+
+    pub fn describe(&self) -> String {
+        if self.fast {
+            format!("{} Hz fast", self.rate)
+        } else {
+            format!("{} Hz x{}", self.rate, self.speed)
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use nannou::prelude::*;
+
+// Balls that move as one rigid body. After the other constraints each substep they are
+// put back in their original arrangement, at the position and angle that best fit where
+// they were pushed, so contacts on any member move and turn the whole cluster.
+#[derive(Debug, Clone)]
+pub struct RigidCluster {
+    // Indices into `Solver::balls`
+    pub balls: Vec<usize>,
+    // Rest positions relative to the centre of mass
+    pub offsets: Vec<Vec2>,
+    // Share of the mass carried by each ball
+    pub weights: Vec<f32>,
+}
+
+impl RigidCluster {
+    // Masses are taken as proportional to area, like `FrameStats::kinetic_energy`
+    pub fn new(balls: Vec<usize>, positions: &[Vec2], radii: &[f32]) -> Self {
+        let total: f32 = radii.iter().map(|r| r * r).sum();
+        let weights: Vec<f32> = radii.iter().map(|r| r * r / total).collect();
+        let center = centroid(positions, &weights);
+        RigidCluster {
+            balls,
+            offsets: positions.iter().map(|p| *p - center).collect(),
+            weights,
+        }
+    }
+
+    // Line for a scene file, with the members given as positions in the list of saved
+    // balls, see `parse_cluster`
+    pub fn describe(&self, balls: &[usize]) -> String {
+        let balls: Vec<String> = balls.iter().map(|i| i.to_string()).collect();
+        let offsets: Vec<String> = self
+            .offsets
+            .iter()
+            .map(|o| format!("{},{}", o.x, o.y))
+            .collect();
+        let weights: Vec<String> = self.weights.iter().map(|w| w.to_string()).collect();
+        format!(
+            "cluster balls={} offsets={} weights={}",
+            balls.join(","),
+            offsets.join(";"),
+            weights.join(",")
+        )
+    }
+
+    // Moves `positions` to the nearest rigid placement of the cluster
+    pub fn solve(&self, positions: &mut [Vec2]) {
+        let center = centroid(positions, &self.weights);
+        // Angle that best lines the offsets up with where the balls are now
+        let (mut sin, mut cos) = (0., 0.);
+        for ((offset, pos), weight) in self.offsets.iter().zip(positions.iter()).zip(&self.weights)
+        {
+            let current = *pos - center;
+            sin += weight * offset.perp_dot(current);
+            cos += weight * offset.dot(current);
+        }
+        let angle = sin.atan2(cos);
+        for (pos, offset) in positions.iter_mut().zip(self.offsets.iter()) {
+            *pos = center + offset.rotate(angle);
+        }
+    }
+
+    // Forgets a ball, keeping the rest where they are relative to each other
+    pub fn remove(&mut self, index: usize) {
+        if let Some(i) = self.balls.iter().position(|b| *b == index) {
+            self.balls.remove(i);
+            self.offsets.remove(i);
+            self.weights.remove(i);
+            let total: f32 = self.weights.iter().sum();
+            for w in self.weights.iter_mut() {
+                *w /= total;
+            }
+            let center = centroid(&self.offsets, &self.weights);
+            for offset in self.offsets.iter_mut() {
+                *offset -= center;
+            }
+        }
+    }
+}
+
+fn centroid(positions: &[Vec2], weights: &[f32]) -> Vec2 {
+    positions
+        .iter()
+        .zip(weights)
+        .fold(Vec2::ZERO, |sum, (p, w)| sum + *p * *w)
+}
+
+// Ball offsets for some common grain shapes, made of balls of `radius` just touching
+// their neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterShape {
+    Dumbbell,
+    Triangle,
+    LShape,
+}
+
+impl ClusterShape {
+    pub fn next(&self) -> Self {
+        match self {
+            ClusterShape::Dumbbell => ClusterShape::Triangle,
+            ClusterShape::Triangle => ClusterShape::LShape,
+            ClusterShape::LShape => ClusterShape::Dumbbell,
+        }
+    }
+
+    pub fn offsets(&self, radius: f32) -> Vec<Vec2> {
+        let d = 2. * radius;
+        match self {
+            ClusterShape::Dumbbell => vec![Vec2::new(-radius, 0.), Vec2::new(radius, 0.)],
+            ClusterShape::Triangle => (0..3)
+                .map(|i| Vec2::new(0., d / 3f32.sqrt()).rotate(i as f32 * 2. * PI / 3.))
+                .collect(),
+            ClusterShape::LShape => vec![
+                Vec2::new(0., 2. * d),
+                Vec2::new(0., d),
+                Vec2::ZERO,
+                Vec2::new(d, 0.),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> (RigidCluster, Vec<Vec2>) {
+        let positions: Vec<Vec2> = ClusterShape::Triangle
+            .offsets(4.)
+            .iter()
+            .map(|o| *o + Vec2::new(10., 20.))
+            .collect();
+        let cluster = RigidCluster::new(vec![3, 4, 5], &positions, &[4., 4., 4.]);
+        (cluster, positions)
+    }
+
+    #[test]
+    fn solve_keeps_a_rigid_placement() {
+        let (cluster, positions) = triangle();
+        let mut moved: Vec<Vec2> = positions
+            .iter()
+            .map(|p| (*p - Vec2::new(10., 20.)).rotate(0.7) + Vec2::new(-5., 3.))
+            .collect();
+        let expected = moved.clone();
+        cluster.solve(&mut moved);
+        for (a, b) in moved.iter().zip(expected.iter()) {
+            assert!(a.abs_diff_eq(*b, 1e-4));
+        }
+    }
+
+    #[test]
+    fn solve_restores_the_shape_and_keeps_the_centre() {
+        let (cluster, positions) = triangle();
+        let mut pushed = positions.clone();
+        pushed[0] += Vec2::new(3., 0.);
+        let center = centroid(&pushed, &cluster.weights);
+        cluster.solve(&mut pushed);
+        assert!(centroid(&pushed, &cluster.weights).abs_diff_eq(center, 1e-4));
+        for i in 0..3 {
+            for j in 0..3 {
+                let rest = positions[i].distance(positions[j]);
+                assert!((pushed[i].distance(pushed[j]) - rest).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn heavier_balls_move_less() {
+        let positions = [Vec2::new(-5., 0.), Vec2::new(5., 0.)];
+        let cluster = RigidCluster::new(vec![0, 1], &positions, &[1., 3.]);
+        assert!((cluster.weights[0] - 0.1).abs() < 1e-6);
+        let mut pushed = positions;
+        pushed[0].x -= 2.;
+        pushed[1].x -= 2.;
+        pushed[1].y += 1.;
+        let before = pushed;
+        cluster.solve(&mut pushed);
+        assert!((pushed[0].distance(pushed[1]) - 10.).abs() < 1e-4);
+        // The light ball is corrected further than the heavy one
+        assert!(pushed[0].distance(before[0]) > pushed[1].distance(before[1]));
+    }
+
+    #[test]
+    fn remove_renormalises_the_rest() {
+        let (mut cluster, _) = triangle();
+        cluster.remove(4);
+        assert_eq!(cluster.balls, vec![3, 5]);
+        assert!((cluster.weights.iter().sum::<f32>() - 1.).abs() < 1e-6);
+        assert!(centroid(&cluster.offsets, &cluster.weights).abs_diff_eq(Vec2::ZERO, 1e-4));
+    }
+}
@@ -0,0 +1,284 @@
+use nannou::prelude::*;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Baked colormaps on disk, so a reveal can skip the first pass.
+//
+// Layout, little endian:
+//   b"FCMAP" u8 version
+//   u64 scene hash, u32 ball count, u32 frame count
+//   u32 spawner count, then u32 max spawn per spawner, in spawn order
+//   u32 length + utf8 source image path
+//   ball count * frame count RGBA8 colours, frame by frame in spawn order
+
+const MAGIC: &[u8; 5] = b"FCMAP";
+const VERSION: u8 = 1;
+
+// 64-bit FNV-1a, which unlike `DefaultHasher` is stable between builds
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColormapHeader {
+    pub scene_hash: u64,
+    pub ball_count: usize,
+    pub frame_count: usize,
+    // How many balls each spawner contributes, in the order their indices were handed out
+    pub spawn_order: Vec<usize>,
+    pub source: String,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u32<W: Write>(w: &mut W, value: usize) -> io::Result<()> {
+    w.write_all(&(value as u32).to_le_bytes())
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<usize> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+pub fn save_colormap<P: AsRef<Path>>(
+    path: P,
+    header: &ColormapHeader,
+    frames: &[Vec<Rgba>],
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(MAGIC)?;
+    w.write_all(&[VERSION])?;
+    w.write_all(&header.scene_hash.to_le_bytes())?;
+    write_u32(&mut w, header.ball_count)?;
+    write_u32(&mut w, frames.len())?;
+    write_u32(&mut w, header.spawn_order.len())?;
+    for count in header.spawn_order.iter() {
+        write_u32(&mut w, *count)?;
+    }
+    write_u32(&mut w, header.source.len())?;
+    w.write_all(header.source.as_bytes())?;
+
+    for frame in frames {
+        if frame.len() != header.ball_count {
+            return Err(invalid(format!(
+                "colormap frame has {} colours but the header says {}",
+                frame.len(),
+                header.ball_count
+            )));
+        }
+        for color in frame {
+            let to_byte = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+            w.write_all(&[
+                to_byte(color.red),
+                to_byte(color.green),
+                to_byte(color.blue),
+                to_byte(color.alpha),
+            ])?;
+        }
+    }
+    w.flush()
+}
+
+// Reads a colormap file, rejecting it unless it was baked for the scene with `scene_hash`
+// and the same spawners
+pub fn load_colormap<P: AsRef<Path>>(
+    path: P,
+    scene_hash: u64,
+    spawn_order: &[usize],
+) -> io::Result<(ColormapHeader, Vec<Vec<Rgba>>)> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut r = BufReader::new(file);
+    let mut magic = [0; 6];
+    r.read_exact(&mut magic)?;
+    if &magic[..5] != MAGIC {
+        return Err(invalid("not a colormap file".to_string()));
+    }
+    if magic[5] != VERSION {
+        return Err(invalid(format!(
+            "unsupported colormap version {}",
+            magic[5]
+        )));
+    }
+
+    let mut hash = [0; 8];
+    r.read_exact(&mut hash)?;
+    let file_hash = u64::from_le_bytes(hash);
+    if file_hash != scene_hash {
+        return Err(invalid(format!(
+            "colormap was baked for scene {:016x}, not {:016x}",
+            file_hash, scene_hash
+        )));
+    }
+
+    let ball_count = read_u32(&mut r)?;
+    let frame_count = read_u32(&mut r)?;
+    if frame_count == 0 {
+        return Err(invalid("colormap has no frames".to_string()));
+    }
+    let num_spawners = read_u32(&mut r)?;
+    let file_spawn_order = (0..num_spawners)
+        .map(|_| read_u32(&mut r))
+        .collect::<io::Result<Vec<_>>>()?;
+    if file_spawn_order != spawn_order {
+        return Err(invalid(format!(
+            "colormap spawn order {:?} does not match {:?}",
+            file_spawn_order, spawn_order
+        )));
+    }
+    if ball_count < spawn_order.iter().sum() {
+        return Err(invalid(format!(
+            "colormap has {} colours, fewer than the {} balls spawned",
+            ball_count,
+            spawn_order.iter().sum::<usize>()
+        )));
+    }
+    let source_len = read_u32(&mut r)?;
+    // The counts are checked against the file's length before anything is allocated
+    // from them
+    let expected_len = (6 + 8 + 4 + 4 + 4 + 4 * num_spawners as u64 + 4 + source_len as u64)
+        .checked_add(
+            (ball_count as u64)
+                .saturating_mul(frame_count as u64)
+                .saturating_mul(4),
+        );
+    if expected_len != Some(file_len) {
+        return Err(invalid(format!(
+            "colormap is {} bytes, but its header describes {}",
+            file_len,
+            expected_len.map_or("more".to_string(), |len| len.to_string())
+        )));
+    }
+    let mut source = vec![0; source_len];
+    r.read_exact(&mut source)?;
+    let source = String::from_utf8(source).map_err(|e| invalid(e.to_string()))?;
+
+    let mut frames = Vec::with_capacity(frame_count);
+    let mut rgba = [0u8; 4];
+    for _ in 0..frame_count {
+        let mut frame = Vec::with_capacity(ball_count);
+        for _ in 0..ball_count {
+            r.read_exact(&mut rgba)?;
+            frame.push(Rgba::new(
+                rgba[0] as f32 / 255.,
+                rgba[1] as f32 / 255.,
+                rgba[2] as f32 / 255.,
+                rgba[3] as f32 / 255.,
+            ));
+        }
+        frames.push(frame);
+    }
+    if r.read(&mut rgba)? != 0 {
+        return Err(invalid("trailing data after colormap".to_string()));
+    }
+
+    let header = ColormapHeader {
+        scene_hash: file_hash,
+        ball_count,
+        frame_count,
+        spawn_order: file_spawn_order,
+        source,
+    };
+    Ok((header, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "fast_circles_{}_{}.fcmap",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn sample() -> (ColormapHeader, Vec<Vec<Rgba>>) {
+        let header = ColormapHeader {
+            scene_hash: 0x1234_5678_9abc_def0,
+            ball_count: 3,
+            frame_count: 2,
+            spawn_order: vec![2, 1],
+            source: "cat.jpg".to_string(),
+        };
+        let frames = vec![
+            vec![
+                Rgba::new(1., 0., 0., 1.),
+                Rgba::new(0., 1., 0., 1.),
+                Rgba::new(0., 0., 1., 1.),
+            ],
+            vec![
+                Rgba::new(0., 0., 0., 0.),
+                Rgba::new(1., 1., 1., 1.),
+                Rgba::new(0.2, 0.4, 0.6, 0.8),
+            ],
+        ];
+        (header, frames)
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round_trip");
+        let (header, frames) = sample();
+        save_colormap(&path, &header, &frames).unwrap();
+        let loaded = load_colormap(&path, header.scene_hash, &header.spawn_order);
+        std::fs::remove_file(&path).unwrap();
+        let (loaded_header, loaded_frames) = loaded.unwrap();
+        assert_eq!(loaded_header, header);
+        for (frame, loaded) in frames.iter().zip(loaded_frames.iter()) {
+            for (a, b) in frame.iter().zip(loaded.iter()) {
+                // Colours are stored as bytes
+                assert!((a.red - b.red).abs() <= 0.5 / 255.);
+                assert!((a.green - b.green).abs() <= 0.5 / 255.);
+                assert!((a.blue - b.blue).abs() <= 0.5 / 255.);
+                assert!((a.alpha - b.alpha).abs() <= 0.5 / 255.);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_another_scene() {
+        let path = temp_path("another_scene");
+        let (header, frames) = sample();
+        save_colormap(&path, &header, &frames).unwrap();
+        let loaded = load_colormap(&path, header.scene_hash + 1, &header.spawn_order);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_a_truncated_file() {
+        let path = temp_path("truncated");
+        let (header, frames) = sample();
+        save_colormap(&path, &header, &frames).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let loaded = load_colormap(&path, header.scene_hash, &header.spawn_order);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_counts_larger_than_the_file() {
+        let path = temp_path("huge_counts");
+        let (header, frames) = sample();
+        save_colormap(&path, &header, &frames).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        // Ball count, then frame count, follow the magic, version and scene hash
+        bytes[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[18..22].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let loaded = load_colormap(&path, header.scene_hash, &header.spawn_order);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::ball::*;
+use nannou::prelude::*;
+
+// Speeds are in world units per substep, the same units as `Ball::impulse`
+
+#[derive(Debug, Clone, Copy)]
+pub struct BallContact {
+    pub a: usize,
+    pub b: usize,
+    pub depth: f32,
+    pub relative_speed: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundaryContact {
+    pub ball: usize,
+    pub depth: f32,
+    pub speed: f32,
+    // Direction the boundary pushed the ball
+    pub normal: Vec2,
+}
+
+// Every contact resolved during one `Solver::update`, indexed by position in `Solver::balls`
+#[derive(Default)]
+pub struct ContactLog {
+    pub ball_contacts: Vec<BallContact>,
+    pub boundary_contacts: Vec<BoundaryContact>,
+    // Contacts of any kind per ball
+    pub counts: Vec<usize>,
+    // Summed penetration depth per ball
+    pub pressure: Vec<f32>,
+}
+
+impl ContactLog {
+    pub fn clear(&mut self, num_balls: usize) {
+        self.ball_contacts.clear();
+        self.boundary_contacts.clear();
+        self.counts.clear();
+        self.counts.resize(num_balls, 0);
+        self.pressure.clear();
+        self.pressure.resize(num_balls, 0.);
+    }
+
+    fn count(&mut self, index: usize, depth: f32) {
+        if let Some(count) = self.counts.get_mut(index) {
+            *count += 1;
+            self.pressure[index] += depth;
+        }
+    }
+
+    // Call before the pair is resolved so the overlap is still present
+    pub fn record_pair(&mut self, a: usize, b: usize, ball_a: &Ball, ball_b: &Ball) {
+        let axis = (ball_a.pos - ball_b.pos).normalize_or_zero();
+        let depth = (ball_a.radius + ball_b.radius) - (ball_a.pos - ball_b.pos).length();
+        let vel_a = ball_a.pos - ball_a.prev_pos;
+        let vel_b = ball_b.pos - ball_b.prev_pos;
+        self.ball_contacts.push(BallContact {
+            a,
+            b,
+            depth,
+            relative_speed: (vel_a - vel_b).dot(axis).abs(),
+        });
+        self.count(a, depth);
+        self.count(b, depth);
+    }
+
+    // Call after the constraint is applied, with the position the ball had before it
+    pub fn record_boundary(&mut self, index: usize, before: Vec2, ball: &Ball) {
+        let correction = ball.pos - before;
+        let vel = before - ball.prev_pos;
+        let depth = correction.length();
+        let normal = correction.normalize_or_zero();
+        self.boundary_contacts.push(BoundaryContact {
+            ball: index,
+            depth,
+            speed: vel.dot(normal).abs(),
+            normal,
+        });
+        self.count(index, depth);
+    }
+
+    // Forgets the balls at the sorted indices in `removed`, renumbering the rest to
+    // match `Solver::remove_indices`
+    pub fn remove_indices(&mut self, removed: &[usize]) {
+        let gone = |i: usize| removed.binary_search(&i).is_ok();
+        let renumber = |i: usize| i - removed.partition_point(|r| *r < i);
+        self.ball_contacts.retain(|c| !gone(c.a) && !gone(c.b));
+        for contact in self.ball_contacts.iter_mut() {
+            contact.a = renumber(contact.a);
+            contact.b = renumber(contact.b);
+        }
+        self.boundary_contacts.retain(|c| !gone(c.ball));
+        for contact in self.boundary_contacts.iter_mut() {
+            contact.ball = renumber(contact.ball);
+        }
+        let mut i = 0;
+        self.counts.retain(|_| {
+            i += 1;
+            !gone(i - 1)
+        });
+        let mut i = 0;
+        self.pressure.retain(|_| {
+            i += 1;
+            !gone(i - 1)
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.ball_contacts.len() + self.boundary_contacts.len()
+    }
+
+    pub fn max_depth(&self) -> f32 {
+        self.ball_contacts
+            .iter()
+            .map(|c| c.depth)
+            .chain(self.boundary_contacts.iter().map(|c| c.depth))
+            .fold(0., f32::max)
+    }
+
+    // Fastest approach along a contact normal, between balls or against a boundary
+    pub fn max_speed(&self) -> f32 {
+        self.ball_contacts
+            .iter()
+            .map(|c| c.relative_speed)
+            .chain(self.boundary_contacts.iter().map(|c| c.speed))
+            .fold(0., f32::max)
+    }
+}
@@ -0,0 +1,262 @@
+use crate::boundary::*;
+use crate::sensor::*;
+use crate::solver::*;
+use nannou::prelude::*;
+
+// Pick distance and handle size in screen pixels, so they don't change with zoom
+const PICK_PIXELS: f32 = 8.;
+const HANDLE_PIXELS: f32 = 6.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Select,
+    Circle,
+    Rect,
+    Polygon,
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Select => "select",
+            Tool::Circle => "circle",
+            Tool::Rect => "rect",
+            Tool::Polygon => "polygon",
+        }
+    }
+}
+
+enum Drag {
+    // Offset from the shape's position to the grab point
+    Move(Vec2),
+    Handle(usize),
+}
+
+// Edit mode for the scene's boundaries. Works in world coordinates and leaves the
+// simulation running.
+pub struct Editor {
+    pub enabled: bool,
+    pub tool: Tool,
+    // Index into `Solver::boundaries`
+    pub selected: Option<usize>,
+    drag: Option<Drag>,
+    // Vertices of the polygon being placed
+    pending: Vec<Vec2>,
+    // Index into `sensor_presets` used when a shape is turned into a sensor
+    pub sensor_preset: usize,
+}
+
+fn segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let edge = b - a;
+    let t = ((p - a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0., 1.);
+    (p - (a + edge * t)).length()
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor {
+            enabled: false,
+            tool: Tool::Select,
+            selected: None,
+            drag: None,
+            pending: vec![],
+            sensor_preset: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.drag = None;
+        self.pending.clear();
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "edit: {} (1-4 tools, K kind, X sink, Del delete, A {} sensor, D next sensor)",
+            self.tool.name(),
+            sensor_presets()[self.sensor_preset].0
+        )
+    }
+
+    // Topmost shape whose outline or centre is within `tolerance` of `world`
+    fn pick(&self, solver: &Solver, world: Vec2, tolerance: f32) -> Option<usize> {
+        solver
+            .boundaries
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, bound)| {
+                let near_outline = bound.outline().iter().any(|line| {
+                    line.windows(2)
+                        .any(|pair| segment_distance(world, pair[0], pair[1]) < tolerance)
+                });
+                let near_centre = (bound.pos() - world).length() < tolerance;
+                (near_outline || near_centre).then_some(i)
+            })
+    }
+
+    pub fn mouse_pressed(&mut self, solver: &mut Solver, world: Vec2, zoom: f32) {
+        let tolerance = PICK_PIXELS / zoom;
+        match self.tool {
+            Tool::Select => {
+                // Handles of the selected shape win over picking a new one
+                if let Some(bound) = self.selected.and_then(|i| solver.boundaries.get(i)) {
+                    let handle = bound
+                        .handles()
+                        .iter()
+                        .position(|h| (*h - world).length() < tolerance);
+                    if let Some(handle) = handle {
+                        self.drag = Some(Drag::Handle(handle));
+                        return;
+                    }
+                }
+                self.selected = self.pick(solver, world, tolerance);
+                self.drag = self
+                    .selected
+                    .map(|i| Drag::Move(solver.boundaries[i].pos() - world));
+            }
+            // New shapes are created tiny and sized by dragging their first handle
+            Tool::Circle | Tool::Rect => {
+                let bound: Box<dyn Boundary> = if self.tool == Tool::Circle {
+                    Box::new(CircleBound {
+                        pos: world,
+                        radius: 1.,
+                        kind: BoundaryType::Outer,
+                        sink: false,
+                        filter: Default::default(),
+                    })
+                } else {
+                    Box::new(RectBound {
+                        pos: world,
+                        kind: BoundaryType::Outer,
+                        width: 2.,
+                        height: 2.,
+                        sink: false,
+                        filter: Default::default(),
+                    })
+                };
+                solver.boundaries.push(bound);
+                self.selected = Some(solver.boundaries.len() - 1);
+                self.drag = Some(Drag::Handle(0));
+            }
+            // Clicking near the first vertex closes the polygon
+            Tool::Polygon => {
+                let closes =
+                    self.pending.len() >= 3 && (self.pending[0] - world).length() < tolerance;
+                if closes {
+                    self.finish_polygon(solver);
+                } else {
+                    self.pending.push(world);
+                }
+            }
+        }
+    }
+
+    pub fn mouse_released(&mut self) {
+        self.drag = None;
+    }
+
+    pub fn mouse_moved(&mut self, solver: &mut Solver, world: Vec2) {
+        let bound = match self.selected.and_then(|i| solver.boundaries.get_mut(i)) {
+            Some(bound) => bound,
+            None => return,
+        };
+        match self.drag {
+            Some(Drag::Move(offset)) => bound.set_pos(world + offset),
+            Some(Drag::Handle(handle)) => bound.set_handle(handle, world),
+            None => {}
+        }
+    }
+
+    fn finish_polygon(&mut self, solver: &mut Solver) {
+        if self.pending.len() >= 3 {
+            solver.boundaries.push(Box::new(PolygonBound::from_world(
+                &self.pending,
+                BoundaryType::Outer,
+            )));
+            self.selected = Some(solver.boundaries.len() - 1);
+        }
+        self.pending.clear();
+    }
+
+    // Returns whether the key was used by the editor
+    pub fn key_pressed(&mut self, solver: &mut Solver, key: Key) -> bool {
+        match key {
+            Key::Key1 => self.tool = Tool::Select,
+            Key::Key2 => self.tool = Tool::Circle,
+            Key::Key3 => self.tool = Tool::Rect,
+            Key::Key4 => self.tool = Tool::Polygon,
+            Key::Return => self.finish_polygon(solver),
+            Key::Escape => {
+                self.pending.clear();
+                self.selected = None;
+            }
+            Key::K => {
+                if let Some(bound) = self.selected.and_then(|i| solver.boundaries.get_mut(i)) {
+                    bound.set_kind(match bound.kind() {
+                        BoundaryType::Inner => BoundaryType::Outer,
+                        BoundaryType::Outer => BoundaryType::Inner,
+                    });
+                }
+            }
+            Key::X => {
+                if let Some(bound) = self.selected.and_then(|i| solver.boundaries.get_mut(i)) {
+                    bound.set_sink(!bound.sink());
+                }
+            }
+            // Turns the selected shape into a sensor, which stops it constraining balls
+            Key::A => {
+                let selected = self.selected.filter(|i| *i < solver.boundaries.len());
+                if let (Some(i), true) = (selected, solver.sensors.len() < MAX_SENSORS) {
+                    let shape = solver.remove_boundary(i);
+                    let actions = sensor_presets().swap_remove(self.sensor_preset).1;
+                    solver.add_sensor(Sensor::new(shape, actions));
+                    self.selected = None;
+                    self.drag = None;
+                }
+            }
+            Key::D => self.sensor_preset = (self.sensor_preset + 1) % sensor_presets().len(),
+            Key::Delete | Key::Back => {
+                if let Some(i) = self.selected.take() {
+                    if i < solver.boundaries.len() {
+                        solver.remove_boundary(i);
+                    }
+                }
+                self.drag = None;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Selection, handles and the polygon in progress, drawn in world coordinates
+    pub fn draw(&self, solver: &Solver, draw: &Draw, zoom: f32) {
+        let size = HANDLE_PIXELS / zoom;
+        if let Some(bound) = self.selected.and_then(|i| solver.boundaries.get(i)) {
+            for line in bound.outline() {
+                draw.polyline()
+                    .stroke_weight(2. / zoom)
+                    .color(YELLOW)
+                    .points(line);
+            }
+            draw.rect()
+                .xy(bound.pos())
+                .w_h(size, size)
+                .no_fill()
+                .stroke_weight(1. / zoom)
+                .stroke(YELLOW);
+            for handle in bound.handles() {
+                draw.rect().xy(handle).w_h(size, size).color(YELLOW);
+            }
+        }
+        if !self.pending.is_empty() {
+            draw.polyline()
+                .stroke_weight(1. / zoom)
+                .color(CYAN)
+                .points(self.pending.iter().copied());
+            for point in self.pending.iter() {
+                draw.rect().xy(*point).w_h(size, size).color(CYAN);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::solver::*;
+use nannou::prelude::*;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub struct SvgOptions {
+    // World rectangle to export, everything outside it is cropped
+    pub viewport: Rect,
+    pub background: Option<Rgba>,
+    // Outline drawn around each ball, if any
+    pub ball_stroke: Option<(Rgba, f32)>,
+    pub boundary_stroke: Option<(Rgba, f32)>,
+}
+
+impl SvgOptions {
+    // Default styling, exporting `viewport`
+    pub fn new(viewport: Rect) -> Self {
+        SvgOptions {
+            viewport,
+            background: Some(Rgba::new(0., 0., 0., 1.)),
+            ball_stroke: None,
+            boundary_stroke: Some((Rgba::new(1., 1., 1., 1.), 1.)),
+        }
+    }
+}
+
+fn svg_color(color: Rgba) -> String {
+    let to_byte = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        to_byte(color.red),
+        to_byte(color.green),
+        to_byte(color.blue)
+    )
+}
+
+fn svg_stroke(stroke: Option<(Rgba, f32)>) -> String {
+    match stroke {
+        Some((color, width)) => format!(
+            r#"stroke="{}" stroke-width="{}" stroke-opacity="{}""#,
+            svg_color(color),
+            width,
+            color.alpha
+        ),
+        None => r#"stroke="none""#.to_string(),
+    }
+}
+
+// Writes the balls and boundary outlines as an SVG document. SVG's y axis points down,
+// so world coordinates are flipped about the viewport.
+pub fn write_svg<P: AsRef<Path>>(path: P, solver: &Solver, options: &SvgOptions) -> io::Result<()> {
+    let view = options.viewport;
+    let to_svg = |p: Vec2| Vec2::new(p.x - view.left(), view.top() - p.y);
+    let mut w = BufWriter::new(File::create(path)?);
+
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        view.w(),
+        view.h(),
+        view.w(),
+        view.h()
+    )?;
+    if let Some(background) = options.background {
+        writeln!(
+            w,
+            r#"<rect width="100%" height="100%" fill="{}"/>"#,
+            svg_color(background)
+        )?;
+    }
+
+    writeln!(w, "<g {}>", svg_stroke(options.ball_stroke))?;
+    for ball in solver.balls.iter() {
+        let ball = ball.borrow();
+        // Skip balls entirely outside the crop
+        if !view.pad(-ball.radius).contains(ball.pos) {
+            continue;
+        }
+        let pos = to_svg(ball.pos);
+        let color = Rgba::from(ball.color);
+        writeln!(
+            w,
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+            pos.x,
+            pos.y,
+            ball.radius,
+            svg_color(color)
+        )?;
+    }
+    writeln!(w, "</g>")?;
+
+    if options.boundary_stroke.is_some() {
+        writeln!(
+            w,
+            r#"<g fill="none" {}>"#,
+            svg_stroke(options.boundary_stroke)
+        )?;
+        for bound in solver.boundaries.iter() {
+            for line in bound.outline() {
+                let points: Vec<String> = line
+                    .iter()
+                    .map(|p| {
+                        let p = to_svg(*p);
+                        format!("{},{}", p.x, p.y)
+                    })
+                    .collect();
+                writeln!(w, r#"<polyline points="{}"/>"#, points.join(" "))?;
+            }
+        }
+        writeln!(w, "</g>")?;
+    }
+
+    writeln!(w, "</svg>")?;
+    w.flush()
+}
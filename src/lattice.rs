@@ -0,0 +1,174 @@
+use crate::link::*;
+use nannou::prelude::*;
+use std::collections::HashMap;
+
+// Points and triangles of a mesh of balls, before it is added to a solver
+pub struct Lattice {
+    pub points: Vec<Vec2>,
+    // Indices into `points`, counter-clockwise
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl Lattice {
+    // Triangular lattice with `spacing` between neighbours, clipped to a disc
+    pub fn disc(center: Vec2, radius: f32, spacing: f32) -> Self {
+        let n = (radius / spacing) as i32 + 1;
+        let row_height = spacing * 3f32.sqrt() / 2.;
+        let mut index = HashMap::new();
+        let mut points = vec![];
+        // Axial coordinates: q steps along x, r steps up and half a spacing right
+        for r in -n..=n {
+            for q in -2 * n..=2 * n {
+                let pos =
+                    center + Vec2::new((q as f32 + r as f32 / 2.) * spacing, r as f32 * row_height);
+                if (pos - center).length() <= radius {
+                    index.insert((q, r), points.len());
+                    points.push(pos);
+                }
+            }
+        }
+        let mut triangles = vec![];
+        for (&(q, r), &i) in index.iter() {
+            let right = index.get(&(q + 1, r));
+            let up = index.get(&(q, r + 1));
+            let up_right = index.get(&(q + 1, r + 1));
+            if let (Some(&b), Some(&c)) = (right, up) {
+                triangles.push([i, b, c]);
+            }
+            if let (Some(&b), Some(&c), Some(&d)) = (right, up_right, up) {
+                triangles.push([b, c, d]);
+            }
+        }
+        // Map order is random
+        triangles.sort();
+        Lattice { points, triangles }
+    }
+
+    // `rows` rows of `cols` points `spacing` apart hanging down from `top_left`, each
+    // square split into two triangles
+    pub fn sheet(top_left: Vec2, cols: usize, rows: usize, spacing: f32) -> Self {
+        let points = (0..rows)
+            .flat_map(|r| {
+                (0..cols).map(move |c| top_left + Vec2::new(c as f32, -(r as f32)) * spacing)
+            })
+            .collect();
+        let mut triangles = vec![];
+        for r in 0..rows.saturating_sub(1) {
+            for c in 0..cols.saturating_sub(1) {
+                let (a, b) = (r * cols + c, r * cols + c + 1);
+                let (below_a, below_b) = (a + cols, b + cols);
+                triangles.push([a, below_a, below_b]);
+                triangles.push([a, below_b, b]);
+            }
+        }
+        Lattice { points, triangles }
+    }
+
+    // Each side of a triangle once, lower index first
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        let mut edges: Vec<(usize, usize)> = self
+            .triangles
+            .iter()
+            .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        edges.sort();
+        edges.dedup();
+        edges
+    }
+}
+
+// How the balls of a lattice are held together
+#[derive(Debug, Clone, Copy)]
+pub struct SoftBody {
+    pub ball_radius: f32,
+    pub color: Hsv,
+    // Kind of the links along the lattice edges
+    pub link: LinkKind,
+    // Stretch, as a fraction of the rest length, beyond which a link snaps
+    pub max_strain: Option<f32>,
+    // Stiffness of the constraints holding each triangle's area, or 0 for none
+    pub area_stiffness: f32,
+}
+
+impl SoftBody {
+    // Springy and volume preserving
+    pub fn jelly(ball_radius: f32, color: Hsv) -> Self {
+        SoftBody {
+            ball_radius,
+            color,
+            link: LinkKind::Spring {
+                stiffness: 0.3,
+                damping: 0.05,
+            },
+            max_strain: None,
+            area_stiffness: 0.5,
+        }
+    }
+
+    // Stretch limited and tearable, free to fold
+    pub fn cloth(ball_radius: f32, color: Hsv) -> Self {
+        SoftBody {
+            ball_radius,
+            color,
+            link: LinkKind::Rope,
+            max_strain: Some(0.5),
+            area_stiffness: 0.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_area(lattice: &Lattice, t: &[usize; 3]) -> f32 {
+        let [a, b, c] = t.map(|i| lattice.points[i]);
+        (b - a).perp_dot(c - a) / 2.
+    }
+
+    #[test]
+    fn sheet_edges_are_shared_once() {
+        let lattice = Lattice::sheet(Vec2::new(-10., 10.), 3, 2, 5.);
+        assert_eq!(lattice.points.len(), 6);
+        assert_eq!(lattice.triangles.len(), 4);
+        let edges = lattice.edges();
+        // Four along the rows, three down the columns and one diagonal per square
+        assert_eq!(edges.len(), 9);
+        assert!(edges.iter().all(|(a, b)| a < b));
+        assert!(edges.windows(2).all(|w| w[0] < w[1]));
+        for (a, b) in edges {
+            let length = lattice.points[a].distance(lattice.points[b]);
+            assert!((length - 5.).abs() < 1e-4 || (length - 5. * 2f32.sqrt()).abs() < 1e-4);
+        }
+        for t in lattice.triangles.iter() {
+            assert!((signed_area(&lattice, t) - 12.5).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn disc_edges_are_all_one_spacing_long() {
+        let center = Vec2::new(30., -20.);
+        let lattice = Lattice::disc(center, 22., 6.);
+        assert!(lattice.points.iter().all(|p| p.distance(center) <= 22.));
+        let edges = lattice.edges();
+        assert!(!edges.is_empty());
+        for (a, b) in edges.iter() {
+            let length = lattice.points[*a].distance(lattice.points[*b]);
+            assert!((length - 6.).abs() < 1e-3);
+        }
+        let area = 6. * 6. * 3f32.sqrt() / 4.;
+        for t in lattice.triangles.iter() {
+            assert!((signed_area(&lattice, t) - area).abs() < 1e-2);
+        }
+        // A triangulated disc has no holes, so Euler's formula holds
+        let (v, e, f) = (lattice.points.len(), edges.len(), lattice.triangles.len());
+        assert_eq!(v + f, e + 1);
+    }
+
+    #[test]
+    fn degenerate_sheets_have_no_edges() {
+        assert!(Lattice::sheet(Vec2::ZERO, 1, 4, 5.).edges().is_empty());
+        assert!(Lattice::sheet(Vec2::ZERO, 0, 0, 5.).points.is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::ball::*;
+use nannou::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    // Holds the ends exactly `length` apart
+    Rigid,
+    // Removes `stiffness` of the length error each substep, from 0 to 1, and `damping`
+    // of the speed at which the ends move apart or together
+    Spring { stiffness: f32, damping: f32 },
+    // Stops the ends getting further apart than `length`, and leaves them free closer in
+    Rope,
+}
+
+impl LinkKind {
+    pub fn describe(&self) -> String {
+        match self {
+            LinkKind::Rigid => "rigid".to_string(),
+            LinkKind::Spring { stiffness, damping } => format!("spring:{},{}", stiffness, damping),
+            LinkKind::Rope => "rope".to_string(),
+        }
+    }
+}
+
+// What the second end of a link is attached to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    // Index into `Solver::balls`
+    Ball(usize),
+    // Fixed world position
+    Point(Vec2),
+    // Point at `offset` from the position of `Solver::boundaries[index]`, following it
+    // as it moves
+    Boundary { index: usize, offset: Vec2 },
+}
+
+// Distance constraint between a ball and an anchor, solved after collisions every substep
+#[derive(Debug, Clone, Copy)]
+pub struct Link {
+    // Index into `Solver::balls`
+    pub a: usize,
+    pub b: Anchor,
+    pub length: f32,
+    pub kind: LinkKind,
+    // Stretch, as a fraction of `length`, beyond which the link snaps
+    pub max_strain: Option<f32>,
+}
+
+impl Link {
+    pub fn new(a: usize, b: Anchor, length: f32, kind: LinkKind) -> Self {
+        Link {
+            a,
+            b,
+            length,
+            kind,
+            max_strain: None,
+        }
+    }
+
+    // Line for a scene file, with `a` and any ball anchor given as positions in the list
+    // of saved balls, see `parse_link`
+    pub fn describe(&self, a: usize, b: Anchor) -> String {
+        let anchor = match b {
+            Anchor::Ball(b) => format!("ball={}", b),
+            Anchor::Point(point) => format!("point={},{}", point.x, point.y),
+            Anchor::Boundary { index, offset } => {
+                format!("boundary={} offset={},{}", index, offset.x, offset.y)
+            }
+        };
+        let mut line = format!(
+            "link a={} {} length={} kind={}",
+            a,
+            anchor,
+            self.length,
+            self.kind.describe()
+        );
+        if let Some(max_strain) = self.max_strain {
+            line += &format!(" max_strain={}", max_strain);
+        }
+        line
+    }
+
+    fn snaps(&self, dist: f32) -> bool {
+        self.max_strain
+            .is_some_and(|max| dist - self.length > max * self.length)
+    }
+
+    // Amount to shorten the link by this substep
+    fn correction(&self, dist: f32) -> f32 {
+        let error = dist - self.length;
+        match self.kind {
+            LinkKind::Rigid => error,
+            LinkKind::Spring { stiffness, .. } => error * stiffness,
+            LinkKind::Rope => error.max(0.),
+        }
+    }
+
+    fn damping(&self) -> f32 {
+        match self.kind {
+            LinkKind::Spring { damping, .. } => damping,
+            _ => 0.,
+        }
+    }
+
+    // Dynamic balls share the correction equally, and others don't move. Returns false,
+    // leaving the balls alone, if the link snapped.
+    pub fn solve_pair(&self, a: &mut Ball, b: &mut Ball) -> bool {
+        let delta = b.pos - a.pos;
+        if self.snaps(delta.length()) {
+            return false;
+        }
+        let dir = match delta.try_normalize() {
+            Some(dir) => dir,
+            None => return true,
+        };
+        let (mobility_a, mobility_b) = (a.mobility(), b.mobility());
+        if mobility_a + mobility_b == 0. {
+            return true;
+        }
+        let share = mobility_a / (mobility_a + mobility_b);
+        // Speed at which the ends are moving apart, measured before the correction
+        let stretching = (b.pos - b.prev_pos - (a.pos - a.prev_pos)).dot(dir);
+        let shift = dir * self.correction(delta.length());
+        a.pos += shift * share;
+        b.pos -= shift * (1. - share);
+        let damp = dir * (self.damping() * stretching);
+        a.impulse(damp * share);
+        b.impulse(-damp * (1. - share));
+        true
+    }
+
+    // Only the ball moves, the anchor point is fixed
+    pub fn solve_pin(&self, a: &mut Ball, point: Vec2) -> bool {
+        let delta = point - a.pos;
+        if self.snaps(delta.length()) {
+            return false;
+        }
+        let dir = match delta.try_normalize() {
+            Some(dir) => dir,
+            None => return true,
+        };
+        if a.mobility() == 0. {
+            return true;
+        }
+        let stretching = -(a.pos - a.prev_pos).dot(dir);
+        a.pos += dir * self.correction(delta.length());
+        a.impulse(dir * (self.damping() * stretching));
+        true
+    }
+
+    // Index of the other ball, if the link joins two balls
+    pub fn other(&self) -> Option<usize> {
+        match self.b {
+            Anchor::Ball(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+// Keeps the area of a polygon of balls near what it started at, so a lattice holds its
+// volume when squashed
+#[derive(Debug, Clone)]
+pub struct AreaConstraint {
+    // Indices into `Solver::balls`, in order around the polygon
+    pub balls: Vec<usize>,
+    // Signed area to hold, positive for counter-clockwise order
+    pub area: f32,
+    // Fraction of the area error removed each substep, from 0 to 1
+    pub stiffness: f32,
+}
+
+pub fn signed_area(points: &[Vec2]) -> f32 {
+    let n = points.len();
+    0.5 * (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum::<f32>()
+}
+
+impl AreaConstraint {
+    // Line for a scene file, with the corners given as positions in the list of saved
+    // balls, see `parse_area`
+    pub fn describe(&self, balls: &[usize]) -> String {
+        let balls: Vec<String> = balls.iter().map(|i| i.to_string()).collect();
+        format!(
+            "area balls={} area={} stiffness={}",
+            balls.join(","),
+            self.area,
+            self.stiffness
+        )
+    }
+
+    // Moves each corner along the gradient of the area, further for corners where the
+    // area changes faster
+    pub fn solve(&self, points: &mut [Vec2]) {
+        let n = points.len();
+        let error = signed_area(points) - self.area;
+        let gradients: Vec<Vec2> = (0..n)
+            .map(|i| {
+                let edge = points[(i + 1) % n] - points[(i + n - 1) % n];
+                0.5 * Vec2::new(edge.y, -edge.x)
+            })
+            .collect();
+        let norm: f32 = gradients.iter().map(|g| g.length_squared()).sum();
+        if norm <= f32::EPSILON {
+            return;
+        }
+        let scale = -self.stiffness * error / norm;
+        for (point, gradient) in points.iter_mut().zip(gradients) {
+            *point += gradient * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::*;
+
+    fn ball_at(x: f32, y: f32) -> Ball {
+        let pos = Vec2::new(x, y);
+        Ball::new(pos, pos, 2., Hsv::new(0., 0., 1.))
+    }
+
+    fn link(length: f32, kind: LinkKind) -> Link {
+        Link::new(0, Anchor::Ball(1), length, kind)
+    }
+
+    #[test]
+    fn rigid_pair_shares_the_correction() {
+        let (mut a, mut b) = (ball_at(0., 0.), ball_at(14., 0.));
+        assert!(link(10., LinkKind::Rigid).solve_pair(&mut a, &mut b));
+        assert!(a.pos.abs_diff_eq(Vec2::new(2., 0.), 1e-5));
+        assert!(b.pos.abs_diff_eq(Vec2::new(12., 0.), 1e-5));
+    }
+
+    #[test]
+    fn fixed_end_does_not_move() {
+        let (mut a, mut b) = (ball_at(0., 0.), ball_at(0., 6.));
+        b.motion = Motion::Fixed;
+        assert!(link(10., LinkKind::Rigid).solve_pair(&mut a, &mut b));
+        assert!(a.pos.abs_diff_eq(Vec2::new(0., -4.), 1e-5));
+        assert_eq!(b.pos, Vec2::new(0., 6.));
+
+        a.motion = Motion::Fixed;
+        let before = a.pos;
+        assert!(link(20., LinkKind::Rigid).solve_pair(&mut a, &mut b));
+        assert_eq!(a.pos, before);
+    }
+
+    #[test]
+    fn rope_only_pulls() {
+        let (mut a, mut b) = (ball_at(0., 0.), ball_at(4., 0.));
+        assert!(link(10., LinkKind::Rope).solve_pair(&mut a, &mut b));
+        assert_eq!((a.pos, b.pos), (Vec2::ZERO, Vec2::new(4., 0.)));
+        let mut b = ball_at(16., 0.);
+        assert!(link(10., LinkKind::Rope).solve_pair(&mut a, &mut b));
+        assert!((a.pos.distance(b.pos) - 10.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn spring_removes_part_of_the_error_and_damps() {
+        let spring = LinkKind::Spring {
+            stiffness: 0.5,
+            damping: 1.,
+        };
+        let mut a = ball_at(0., 0.);
+        let mut b = ball_at(14., 0.);
+        // Moving apart at 2 units per substep
+        b.prev_pos = Vec2::new(12., 0.);
+        assert!(link(10., spring).solve_pair(&mut a, &mut b));
+        assert!((a.pos.distance(b.pos) - 12.).abs() < 1e-5);
+        let relative = (b.pos - b.prev_pos) - (a.pos - a.prev_pos);
+        // The ends were moving apart at 2, the correction pulls them 2 closer and the
+        // damping takes away the original 2
+        assert!((relative.x + 2.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn overstretched_link_snaps() {
+        let mut snapping = link(10., LinkKind::Rigid);
+        snapping.max_strain = Some(0.5);
+        let (mut a, mut b) = (ball_at(0., 0.), ball_at(16., 0.));
+        assert!(!snapping.solve_pair(&mut a, &mut b));
+        assert_eq!((a.pos, b.pos), (Vec2::ZERO, Vec2::new(16., 0.)));
+        let mut b = ball_at(14., 0.);
+        assert!(snapping.solve_pair(&mut a, &mut b));
+    }
+
+    #[test]
+    fn pin_moves_only_the_ball() {
+        let pin = Link::new(0, Anchor::Point(Vec2::new(0., 10.)), 4., LinkKind::Rigid);
+        let mut a = ball_at(0., 0.);
+        assert!(pin.solve_pin(&mut a, Vec2::new(0., 10.)));
+        assert!(a.pos.abs_diff_eq(Vec2::new(0., 6.), 1e-5));
+
+        let mut rope = pin;
+        rope.kind = LinkKind::Rope;
+        let mut a = ball_at(0., 8.);
+        assert!(rope.solve_pin(&mut a, Vec2::new(0., 10.)));
+        assert_eq!(a.pos, Vec2::new(0., 8.));
+
+        rope.max_strain = Some(1.);
+        let mut a = ball_at(0., -1.);
+        assert!(!rope.solve_pin(&mut a, Vec2::new(0., 10.)));
+        assert_eq!(a.pos, Vec2::new(0., -1.));
+    }
+
+    #[test]
+    fn pin_leaves_kinematic_balls_on_their_path() {
+        let pin = Link::new(0, Anchor::Point(Vec2::ZERO), 4., LinkKind::Rigid);
+        let mut a = ball_at(10., 0.);
+        a.motion = Motion::Kinematic(Path::Circle {
+            center: Vec2::ZERO,
+            radius: 10.,
+            speed: 0.1,
+            phase: 0.,
+        });
+        assert!(pin.solve_pin(&mut a, Vec2::ZERO));
+        assert_eq!(a.pos, Vec2::new(10., 0.));
+    }
+
+    #[test]
+    fn area_constraint_restores_the_area() {
+        let mut points = vec![Vec2::ZERO, Vec2::new(4., 0.), Vec2::new(0., 3.)];
+        let area = AreaConstraint {
+            balls: vec![0, 1, 2],
+            area: signed_area(&points),
+            stiffness: 1.,
+        };
+        assert_eq!(area.area, 6.);
+        points[1].x = 2.;
+        // The area is quadratic in the positions, so one linear step gets close but not exact
+        for _ in 0..4 {
+            area.solve(&mut points);
+        }
+        assert!((signed_area(&points) - 6.).abs() < 1e-3);
+    }
+}
@@ -1,23 +1,126 @@
 use nannou::prelude::*;
 mod ball;
+mod cli;
+mod clock;
+use std::cell::Cell;
 use std::time::Instant;
 mod boundary;
+mod brush;
+mod camera;
+mod cluster;
+mod colormap;
+mod contact;
+mod editor;
+mod export;
+mod lattice;
+mod link;
+mod motion;
+mod overlay;
+mod palette;
+mod params;
 mod partition;
+mod raster;
+mod record;
+mod reveal;
+mod scene;
+mod sdf;
+mod sensor;
 mod solver;
 mod spawn;
+mod stats;
+use ball::*;
 use boundary::*;
-use nannou::image::io::Reader;
+use brush::*;
+use camera::*;
+use cli::*;
+use clock::*;
+use cluster::*;
+use colormap::*;
+use editor::*;
+use export::*;
+use lattice::*;
+use link::*;
+use motion::*;
 use nannou::image::{DynamicImage, GenericImageView};
+use overlay::*;
+use palette::*;
+use params::*;
 use partition::*;
+use record::*;
+use reveal::*;
+use scene::*;
+use sdf::*;
 use solver::*;
 use spawn::*;
+use stats::*;
 use std::fs;
 use std::io::Cursor;
 use std::thread;
 use std::thread::sleep;
 
 fn main() {
-    nannou::app(model).update(update).simple_window(view).run();
+    let cli = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if cli.headless {
+        run_headless(&cli);
+    } else {
+        nannou::app(model).update(update).run();
+    }
+}
+
+// Simulates without a window and writes whatever exports were asked for
+fn run_headless(cli: &CliArgs) {
+    let mut model = build_model(cli);
+    // A recorder that fails is reported and dropped, and the simulation carries on
+    let mut recorders = vec![];
+    if let Some(path) = &cli.gif {
+        match Recorder::gif(path, cli.record_options(model.world)) {
+            Ok(recorder) => recorders.push(recorder),
+            Err(e) => eprintln!("Could not record {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &cli.y4m {
+        match Recorder::y4m(path, cli.record_options(model.world)) {
+            Ok(recorder) => recorders.push(recorder),
+            Err(e) => eprintln!("Could not record {}: {}", path, e),
+        }
+    }
+    for frame in 0..cli.frames {
+        step(&mut model, frame, None);
+        recorders.retain_mut(|recorder| match recorder.capture(&model.solver) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Recording stopped: {}", e);
+                false
+            }
+        });
+        if !recorders.is_empty() && recorders.iter().all(|r| r.finished()) {
+            break;
+        }
+    }
+    for recorder in recorders {
+        if let Err(e) = recorder.finish() {
+            eprintln!("Could not finish recording: {}", e);
+        }
+    }
+    if !model.solver.sensors.is_empty() {
+        eprintln!(
+            "{}",
+            sensor::describe_sensors(&model.solver.sensors, &model.solver.sensor_events)
+        );
+    }
+    if let Some(path) = &cli.svg {
+        let options = SvgOptions::new(cli.crop.unwrap_or(model.world));
+        if let Err(e) = write_svg(path, &model.solver, &options) {
+            eprintln!("Could not write {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
 }
 
 struct Model {
@@ -25,18 +128,66 @@ struct Model {
     timestep: f32,
     ball_radius: f32,
     spawners: Vec<LinearSpawner>,
-    color_image: DynamicImage,
+    color_frames: Vec<DynamicImage>,
+    reveal_mode: RevealMode,
+    baked_frames: usize,
+    reveal_runs: usize,
+    image_name: String,
+    // Palette given by name or file for the colour modes and spawners, if any
+    palette_name: Option<String>,
+    // Palette the revealed colours are snapped to, if any
+    quantize_name: Option<String>,
+    colormap_path: String,
+    scene_hash: u64,
     frames_for_color_reset: usize,
     boundary_time: f32,
     sync_frames: usize,
     sim_runs: usize,
     fps: f32,
     ball_count: usize,
+    recorder: Option<Recorder>,
+    camera: Camera,
+    overlays: Overlays,
+    stats: StatsHistory,
+    show_stats: bool,
+    // Time the last `view` took, which only has shared access to the model
+    draw_ms: Cell<f32>,
+    editor: Editor,
+    brush: Brush,
+    params: ParamPanel,
+    // Scene file that W writes to
+    scene_path: String,
+    clock: SimClock,
+    // Extent of the world in world units, independent of the window
+    world: Rect,
+    // Shape the next U press drops
+    cluster_shape: ClusterShape,
 }
 
 fn model(_app: &App) -> Model {
+    // Already validated in `main`
+    let cli = CliArgs::parse(std::env::args().skip(1)).unwrap();
+    let mut window = _app
+        .new_window()
+        .view(view)
+        .key_pressed(key_pressed)
+        .mouse_wheel(mouse_wheel)
+        .mouse_pressed(mouse_pressed)
+        .mouse_released(mouse_released)
+        .mouse_moved(mouse_moved)
+        .resized(resized);
+    if let Some((width, height)) = cli.window {
+        window = window.size(width, height);
+    }
+    window.build().unwrap();
+    let mut model = build_model(&cli);
+    model.camera.resize(_app.window_rect(), model.world);
+    model
+}
+
+fn build_model(cli: &CliArgs) -> Model {
     let ball_radius = 5.;
-    let image_name = "cat2.jpg";
+    let world = Rect::from_w_h(900., 900.);
     let spawn_period = 1;
     // let num_rows = 880 / (2 * ball_radius as usize);
     let num_rows = 10;
@@ -46,15 +197,37 @@ fn model(_app: &App) -> Model {
     let mut model = Model {
         fps: 0.,
         ball_count: 0,
+        recorder: None,
+        camera: Camera::default(),
+        overlays: Overlays::default(),
+        stats: StatsHistory::new(240),
+        show_stats: false,
+        draw_ms: Cell::new(0.),
+        brush: Brush::new(ball_radius),
+        editor: Editor::new(),
+        params: ParamPanel::default(),
+        scene_path: cli.scene.clone().unwrap_or("scene.txt".to_string()),
+        clock: SimClock::new(cli.rate, cli.max_steps),
+        world,
+        cluster_shape: ClusterShape::Dumbbell,
         ball_radius,
         frames_for_color_reset,
         boundary_time: 0.,
         sync_frames: 0,
         sim_runs: 0,
-        color_image: Reader::open(image_name).unwrap().decode().unwrap(),
+        reveal_mode: RevealMode::Cycle { period: 10 },
+        baked_frames: 0,
+        reveal_runs: 0,
+        // Loaded once the scene and command line have had their say
+        image_name: "cat2.jpg".to_string(),
+        palette_name: None,
+        quantize_name: None,
+        colormap_path: String::new(),
+        scene_hash: 0,
         spawners: vec![
             LinearSpawner::new(
-                Vec2::new(0., 440. - 100.),
+                // Near the top of the world, clear of the container wall
+                Vec2::new(world.x(), world.top() - 110.),
                 -PI / 2.,
                 spawn_period,
                 2.,
@@ -78,48 +251,98 @@ fn model(_app: &App) -> Model {
         solver: Solver {
             gravity: Vec2::new(0.0, 0.),
             balls: Solver::init_balls(ball_radius),
-            hash: SpatialHash::new(ball_radius, 900., 900.),
+            hash: SpatialHash::new(ball_radius, world, cli.periodic),
             substeps: 8,
-            pixel_scale: 900.
-                / Reader::open(image_name)
-                    .unwrap()
-                    .decode()
-                    .unwrap()
-                    .dimensions()
-                    .0 as f32,
+            // Set by `load_image`
+            image_rect: world,
             detect_mode: DetectMode::SpatialPartition,
             colormap: vec![],
+            colormap_frames: vec![],
+            sensor_events: vec![],
+            contacts: None,
+            contacts_wanted: false,
+            color_mode: ColorMode::Colormap,
+            palette: Palette::viridis(),
+            color_range: (0., 1.),
+            image_sampling: ImageSampling::Area,
+            quantize: None,
+            dither: true,
+            stats: FrameStats::default(),
+            time: 0.,
+            setup: Setup::default(),
+            sensors: vec![
+                // Sensor::new(
+                //     Box::new(CircleBound {
+                //         pos: Vec2::new(0., 0.),
+                //         kind: BoundaryType::Outer,
+                //         radius: 100.,
+                //         sink: false,
+                //         filter: CollisionFilter::default(),
+                //     }),
+                //     vec![SensorAction::Remove],
+                // ),
+                // Sensor::new(
+                //     Box::new(RectBound {
+                //         pos: Vec2::new(0., -440.),
+                //         kind: BoundaryType::Outer,
+                //         width: 900.,
+                //         height: 40.,
+                //         sink: false,
+                //         filter: CollisionFilter::default(),
+                //     }),
+                //     vec![SensorAction::Count, SensorAction::Remove],
+                // ),
+            ],
+            // The circle below the container
+            mouse_boundary: Some(1),
+            links: vec![],
+            areas: vec![],
+            clusters: vec![],
             boundaries: vec![
+                // Along periodic axes the walls are moved out of reach of the balls
                 Box::new(RectBound {
-                    pos: Vec2::new(0., 0.),
+                    pos: world.xy(),
                     kind: BoundaryType::Inner,
-                    width: 880.,
-                    height: 880.,
+                    width: if cli.periodic.x {
+                        world.w() + 4. * ball_radius
+                    } else {
+                        world.w() - 20.
+                    },
+                    height: if cli.periodic.y {
+                        world.h() + 4. * ball_radius
+                    } else {
+                        world.h() - 20.
+                    },
                     sink: false,
+                    filter: CollisionFilter::default(),
                 }),
                 // Box::new(CircleBound {
                 //     pos: Vec2::new(0., 0.),
                 //     kind: BoundaryType::Outer,
                 //     radius: 50.,
                 //     sink: false,
+                //     filter: CollisionFilter::default(),
                 // }),
                 // Box::new(CircleBound {
                 //     pos: Vec2::new(0., -220.),
                 //     kind: BoundaryType::Outer,
                 //     radius: 10.,
                 //     sink: false,
+                //     filter: CollisionFilter::default(),
                 // }),
                 Box::new(CircleBound {
                     pos: Vec2::new(-150., 0.),
                     kind: BoundaryType::Outer,
                     radius: 50.,
                     sink: false,
+                    filter: CollisionFilter::default(),
                 }),
                 // Box::new(CircleBound {
                 //     pos: Vec2::new(150., 0.),
                 //     kind: BoundaryType::Outer,
                 //     radius: 50.,
                 //     sink: false,
+                //     filter: CollisionFilter::default(),
                 // }),
                 // Box::new(RectBound {
                 //     pos: Vec2::new(0., 0.),
@@ -127,11 +350,30 @@ fn model(_app: &App) -> Model {
                 //     width: 300.,
                 //     height: 100.,
                 //     sink: false,
+                //     filter: CollisionFilter::default(),
+                // }),
+                // Box::new(CapsuleBound {
+                //     pos: Vec2::new(150., 0.),
+                //     length: 120.,
+                //     angle: PI / 4.,
+                //     radius: 15.,
+                //     kind: BoundaryType::Outer,
+                //     sink: false,
+                //     filter: CollisionFilter::default(),
+                // }),
+                // Box::new(AnnulusBound {
+                //     pos: Vec2::new(0., 0.),
+                //     inner_radius: 250.,
+                //     outer_radius: 400.,
+                //     kind: BoundaryType::Inner,
+                //     sink: false,
+                //     filter: CollisionFilter::default(),
                 // }),
             ],
         },
 
         timestep: 0.0000000011,
+        color_frames: vec![],
     };
 
     // Create funnel also abstract later
@@ -147,28 +389,310 @@ fn model(_app: &App) -> Model {
     //         radius: 15.,
     //         kind: BoundaryType::Outer,
     //         sink: false,
+    //         filter: CollisionFilter::default(),
     //     }));
     //     sign *= -1.;
     // }
+
+    model.params.defaults = Some(Params::read(&model.solver, model.timestep, &model.spawners));
+    if let Some(path) = &cli.scene {
+        match load_scene(path, &mut model.solver) {
+            Ok(rest) => apply_scene_settings(&mut model, &rest),
+            Err(e) => eprintln!("Could not load {}: {}", path, e),
+        }
+    }
+
+    if let Some(image) = &cli.image {
+        model.image_name = image.clone();
+    }
+    if let Some(reveal) = cli.reveal {
+        model.reveal_mode = reveal;
+    }
+    load_image(&mut model);
+    if let Some(palette) = &cli.palette {
+        model.palette_name = Some(palette.clone());
+    }
+    load_palette(&mut model);
+    if let Some(quantize) = &cli.quantize {
+        model.quantize_name = Some(quantize.clone());
+    }
+    if cli.no_dither {
+        model.solver.dither = false;
+    }
+    load_quantize(&mut model);
+
+    // Added on top of the scene's shapes. Dark pixels are solid, and the image spans the
+    // world's width like the colour image.
+    if let Some(path) = &cli.mask {
+        match MaskBound::open(path, 128, BoundaryType::Outer) {
+            Ok(mut mask) => {
+                mask.fit_width(world);
+                model.solver.boundaries.push(Box::new(mask));
+            }
+            Err(e) => eprintln!("Could not load {}: {}", path, e),
+        }
+    }
+
+    // Reuse a colormap baked for this exact scene instead of running the first pass
+    model.scene_hash = scene_hash(&model);
+    let spawn_order: Vec<usize> = model.spawners.iter().map(|s| s.max_spawn()).collect();
+    match load_colormap(&model.colormap_path, model.scene_hash, &spawn_order) {
+        Ok((header, frames)) if header.frame_count == model.color_frames.len() => {
+            model.solver.colormap = frames[0].clone();
+            model.solver.colormap_frames = frames;
+            model.baked_frames = header.frame_count;
+            model.reveal_runs = 1;
+        }
+        Ok(_) => eprintln!("Ignoring {}: frame count differs", model.colormap_path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Ignoring {}: {}", model.colormap_path, e),
+    }
     model
 }
 
+// Loads the still image, animated GIF or directory of numbered frames named by
+// `image_name`. If that fails the balls keep their spawn colours.
+fn load_image(model: &mut Model) {
+    model.colormap_path = format!("{}.fcmap", model.image_name);
+    model.color_frames = match load_frames(&model.image_name) {
+        Ok(frames) if !frames.is_empty() => frames,
+        Ok(_) => {
+            eprintln!("Could not load {}: no frames", model.image_name);
+            vec![]
+        }
+        Err(e) => {
+            eprintln!("Could not load {}: {}", model.image_name, e);
+            vec![]
+        }
+    };
+    // The image spans the width of the world, centred on it
+    if let Some(frame) = model.color_frames.first() {
+        let (width, height) = frame.dimensions();
+        let world = model.world;
+        model.solver.image_rect = Rect::from_x_y_w_h(
+            world.x(),
+            world.y(),
+            world.w(),
+            world.w() * height as f32 / width as f32,
+        );
+    }
+}
+
+// Hands the palette named by `palette_name` to the colour modes and the spawners, which
+// spread it over the balls in spawn order
+fn load_palette(model: &mut Model) {
+    let Some(name) = model.palette_name.clone() else {
+        return;
+    };
+    match Palette::open(&name) {
+        Ok(palette) => {
+            for spawner in model.spawners.iter_mut() {
+                spawner.set_palette(palette.clone());
+            }
+            model.solver.palette = palette;
+        }
+        Err(e) => {
+            eprintln!("Could not load palette {}: {}", name, e);
+            model.palette_name = None;
+        }
+    }
+}
+
+// Snaps the revealed colours to the palette named by `quantize_name` from the next bake on
+fn load_quantize(model: &mut Model) {
+    let Some(name) = model.quantize_name.clone() else {
+        model.solver.quantize = None;
+        return;
+    };
+    match Palette::open(&name) {
+        Ok(palette) => model.solver.quantize = Some(palette),
+        Err(e) => {
+            eprintln!("Could not load palette {}: {}", name, e);
+            model.quantize_name = None;
+            model.solver.quantize = None;
+        }
+    }
+}
+
+// Everything that affects where balls settle, as written to scene files
+fn describe_scene(model: &Model) -> String {
+    let mut lines = vec![
+        model.solver.describe(),
+        format!("timestep={}", model.timestep),
+        format!("ball_radius={}", model.ball_radius),
+        format!("frames_for_color_reset={}", model.frames_for_color_reset),
+    ];
+    lines.extend(model.spawners.iter().map(|s| s.describe()));
+    lines.join("\n")
+}
+
+fn scene_hash(model: &Model) -> u64 {
+    fnv1a(describe_scene(model).as_bytes())
+}
+
+// Settings that only change how the balls are coloured, saved with the scene but left
+// out of its hash
+fn describe_colors(model: &Model) -> String {
+    let mut lines = vec![
+        format!("image={}", model.image_name),
+        format!("reveal={}", model.reveal_mode.describe()),
+    ];
+    if let Some(palette) = &model.palette_name {
+        lines.push(format!("palette={}", palette));
+    }
+    if let Some(quantize) = &model.quantize_name {
+        lines.push(format!("quantize={}", quantize));
+    }
+    lines.push(format!("dither={}", model.solver.dither));
+    lines.join("\n")
+}
+
+// Applies the scene file lines that belong to the model rather than the solver.
+// Spawner lines update the spawners in order.
+fn apply_scene_settings(model: &mut Model, lines: &[String]) {
+    let mut spawners = model.spawners.iter_mut();
+    for line in lines {
+        let applied = match line.split_once('=') {
+            Some(("timestep", value)) => value.parse().map(|v| model.timestep = v).is_ok(),
+            Some(("frames_for_color_reset", value)) => value
+                .parse()
+                .map(|v| model.frames_for_color_reset = v)
+                .is_ok(),
+            // The hash grid and spawners are sized from the radius in code
+            Some(("ball_radius", _)) => true,
+            Some(("image", value)) => {
+                model.image_name = value.to_string();
+                true
+            }
+            Some(("palette", value)) => {
+                model.palette_name = Some(value.to_string());
+                true
+            }
+            Some(("quantize", "none")) => {
+                model.quantize_name = None;
+                true
+            }
+            Some(("quantize", value)) => {
+                model.quantize_name = Some(value.to_string());
+                true
+            }
+            Some(("dither", value)) => value.parse().map(|v| model.solver.dither = v).is_ok(),
+            Some(("reveal", value)) => match RevealMode::parse(value) {
+                Ok(mode) => {
+                    model.reveal_mode = mode;
+                    true
+                }
+                Err(_) => false,
+            },
+            // Spawner lines are matched to the spawners in order
+            _ => match Fields::parse(line) {
+                Ok(fields) if fields.name == "linear" => match spawners.next() {
+                    Some(spawner) => {
+                        let velocity = fields.f32("velocity");
+                        let period = fields.usize("period");
+                        let filter = fields.filter();
+                        if let (Ok(velocity), Ok(period), Ok(filter)) = (velocity, period, filter) {
+                            spawner.set_spawn_velocity(velocity);
+                            spawner.set_spawn_period(period);
+                            spawner.set_filter(filter);
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    None => false,
+                },
+                _ => false,
+            },
+        };
+        if !applied {
+            eprintln!("Ignoring scene line: {}", line);
+        }
+    }
+}
+
+// Contact normals, counts and penetration come from the solver's contact log, which is
+// only recorded while something shows them
+fn sync_contacts(model: &mut Model) {
+    model.solver.contacts_wanted = model.overlays.contacts || model.show_stats;
+}
+
+fn save_baked_colormap(model: &Model) {
+    let frames = &model.solver.colormap_frames;
+    let header = ColormapHeader {
+        scene_hash: model.scene_hash,
+        ball_count: frames[0].len(),
+        frame_count: frames.len(),
+        spawn_order: model.spawners.iter().map(|s| s.max_spawn()).collect(),
+        source: model.image_name.clone(),
+    };
+    if let Err(e) = save_colormap(&model.colormap_path, &header, frames) {
+        eprintln!("Could not save {}: {}", model.colormap_path, e);
+    }
+}
+
 fn update(_app: &App, _model: &mut Model, _update: Update) {
-    // Critical Updates
     let now = Instant::now();
-    _model.solver.update(_model.timestep);
-    let frames = _app.elapsed_frames() as usize;
-    if _model.sim_runs < 2 {
-        _model.sync_frames += 1;
+    let mouse = _model.camera.to_world(_app.mouse.position());
+    // The editor and brushes own the mouse while they are on
+    let mouse_free = !_model.editor.enabled && _model.brush.tool.is_none();
+
+    // Fixed-rate steps for the real time that has passed, or as many as fit in the
+    // budget in fast mode
+    let steps = if _model.clock.fast {
+        usize::MAX
+    } else {
+        _model.clock.advance()
+    };
+    let mut taken = 0;
+    while taken < steps && !(_model.clock.fast && now.elapsed() >= _model.clock.fast_budget) {
+        _model
+            .brush
+            .apply(&mut _model.solver, mouse, _model.timestep);
+        step(_model, _model.clock.steps, mouse_free.then_some(mouse));
+        _model.clock.steps += 1;
+        taken += 1;
+
+        if let Some(recorder) = &mut _model.recorder {
+            let result = recorder.capture(&_model.solver);
+            if let Err(e) = result {
+                eprintln!("Recording stopped: {}", e);
+                _model.recorder = None;
+            } else if recorder.finished() {
+                if let Err(e) = _model.recorder.take().unwrap().finish() {
+                    eprintln!("Could not finish recording: {}", e);
+                }
+            }
+        }
     }
+
+    _model.camera.update(&_model.solver);
+    if taken > 0 {
+        let mut stats = _model.solver.stats;
+        stats.phase_ms[Phase::Drawing as usize] = _model.draw_ms.get();
+        _model.stats.push(stats);
+    }
+    _model.fps = _app.fps();
+}
+
+// One frame of simulation, spawning and reveal bookkeeping, shared with headless runs
+fn step(_model: &mut Model, frames: usize, mouse: Option<Vec2>) {
+    // Critical Updates
+    _model.solver.update(_model.timestep);
+    _model.sync_frames += 1;
     _model.boundary_time += 0.01;
 
     // Animations
     let f = 1.;
     let w = -2. * 3.14159 * f;
     let r = 400. - 20.;
-    let mouse_bound = &mut _model.solver.boundaries[1];
-    mouse_bound.set_pos(_app.mouse.position());
+    let mouse_bound = _model
+        .solver
+        .mouse_boundary
+        .and_then(|i| _model.solver.boundaries.get_mut(i));
+    if let (Some(mouse), Some(mouse_bound)) = (mouse, mouse_bound) {
+        mouse_bound.set_pos(mouse);
+    }
 
     // let spawner = &mut _model.spawners[0];
     // let move_bound = &mut _model.solver.boundaries[1];
@@ -180,6 +704,7 @@ fn update(_app: &App, _model: &mut Model, _update: Update) {
     // let first_bound = &mut _model.solver.boundaries[0];
     // first_bound.radius = r + 20. * (_model.boundary_time * 5. * w).sin();
     // Spawning section
+    let now = Instant::now();
     let mut total_spawns_frame = 0;
     for (i, spawner) in _model.spawners.iter_mut().enumerate() {
         total_spawns_frame += spawner.update(
@@ -192,10 +717,28 @@ fn update(_app: &App, _model: &mut Model, _update: Update) {
             &mut _model.solver.colormap,
         );
     }
+    _model.solver.stats.add(Phase::Spawning, now);
 
-    // Color reset
-    if (frames == _model.frames_for_color_reset) && frames > 0 {
-        _model.solver.set_image_colors(&mut _model.color_image);
+    // Color reset, baking one frame of the sequence per run
+    let num_frames = _model.color_frames.len();
+    let needs_reset = match _model.reveal_mode {
+        RevealMode::Cycle { .. } => _model.baked_frames < num_frames,
+        RevealMode::Sequential => true,
+    };
+    let reset = frames.is_multiple_of(_model.frames_for_color_reset) && frames > 0;
+    if reset && needs_reset && num_frames > 0 {
+        let frame = _model.reveal_runs % num_frames;
+        if _model.baked_frames < num_frames {
+            _model
+                .solver
+                .bake_colormap_frame(frame, &mut _model.color_frames[frame]);
+            _model.baked_frames += 1;
+            if _model.baked_frames == num_frames {
+                save_baked_colormap(_model);
+            }
+        }
+        _model.solver.show_colormap_frame(frame);
+        _model.reveal_runs += 1;
         _model.boundary_time = 0.;
         _model.sync_frames = 0;
         _model.ball_count = 0;
@@ -205,40 +748,345 @@ fn update(_app: &App, _model: &mut Model, _update: Update) {
         }
     }
 
+    // Animate the final pile through every baked frame
+    if let RevealMode::Cycle { period } = _model.reveal_mode {
+        if num_frames > 1 && _model.baked_frames == num_frames {
+            _model
+                .solver
+                .show_colormap_frame((_model.sync_frames / period) % num_frames);
+        }
+    }
+
     // Count runs to enable correct resets of state
-    if frames % _model.frames_for_color_reset == 0 && frames > 0 {
+    if reset {
         _model.sim_runs += 1;
     }
 
-    // TODO: Abstract over this
-    // _model
-    //     .solver
-    //     .balls
-    //     .retain(|b| b.borrow().pos.length_squared() > (100f32).pow(2.));
-
-    // _model.solver.balls.retain(|b| b.borrow().pos.y > -420.);
     // Update count
     _model.ball_count = _model.solver.balls.len();
+}
+
+fn key_pressed(_app: &App, _model: &mut Model, key: Key) {
+    if _model.editor.enabled && _model.editor.key_pressed(&mut _model.solver, key) {
+        return;
+    }
+    if _model.params.visible {
+        let mut params = Params::read(&_model.solver, _model.timestep, &_model.spawners);
+        if _model.params.key_pressed(key, &mut params) {
+            params.apply(
+                &mut _model.solver,
+                &mut _model.timestep,
+                &mut _model.spawners,
+            );
+            _model.scene_hash = scene_hash(_model);
+            return;
+        }
+    }
+    match key {
+        Key::Tab => _model.editor.toggle(),
+        Key::Comma => _model.clock.speed = (_model.clock.speed / 2.).max(1. / 16.),
+        Key::Period => _model.clock.speed = (_model.clock.speed * 2.).min(16.),
+        Key::M => _model.clock.fast = !_model.clock.fast,
+        Key::O => _model.params.visible = !_model.params.visible,
+        Key::W => match save_scene(
+            &_model.scene_path,
+            &format!("{}\n{}", describe_scene(_model), describe_colors(_model)),
+        ) {
+            Ok(()) => eprintln!("Wrote {}", _model.scene_path),
+            Err(e) => eprintln!("Could not write {}: {}", _model.scene_path, e),
+        },
+        Key::Z => _model.brush.next_tool(),
+        Key::LBracket => _model.brush.radius = (_model.brush.radius / 1.25).max(2.),
+        Key::RBracket => _model.brush.radius *= 1.25,
+        Key::Minus => _model.brush.ball_radius = (_model.brush.ball_radius - 0.5).max(1.),
+        Key::Equals => _model.brush.ball_radius += 0.5,
+        Key::P => _model.brush.next_color(),
+        Key::L => {
+            // Hangs a rope from the mouse, or a springy chain with shift held. The top is
+            // pinned to the boundary selected in the editor, or else where it was placed.
+            let top = _model.camera.to_world(_app.mouse.position());
+            let radius = _model.brush.ball_radius;
+            let kind = if _app.keys.mods.shift() {
+                LinkKind::Spring {
+                    stiffness: 0.2,
+                    damping: 0.05,
+                }
+            } else {
+                LinkKind::Rope
+            };
+            let rope = _model.solver.add_chain(
+                top,
+                top - Vec2::new(0., 42. * radius),
+                20,
+                radius,
+                Hsv::from(_model.brush.color),
+                kind,
+            );
+            let selected = _model
+                .editor
+                .selected
+                .filter(|i| *i < _model.solver.boundaries.len());
+            match selected {
+                Some(boundary) if _model.editor.enabled => {
+                    _model.solver.pin_to_boundary(rope[0], boundary)
+                }
+                _ => _model.solver.pin_to_point(rope[0]),
+            }
+        }
+        Key::J => {
+            // Drops a jelly blob at the mouse
+            let center = _model.camera.to_world(_app.mouse.position());
+            let radius = _model.brush.ball_radius;
+            let body = SoftBody::jelly(radius, Hsv::from(_model.brush.color));
+            let lattice = Lattice::disc(center, _model.brush.radius, 2.2 * radius);
+            _model.solver.add_soft_body(&lattice, &body);
+        }
+        Key::Y => {
+            // Hangs a tearable cloth from the mouse, pinned along its top edge
+            let top_left = _model.camera.to_world(_app.mouse.position());
+            let radius = _model.brush.ball_radius;
+            let body = SoftBody::cloth(radius, Hsv::from(_model.brush.color));
+            let lattice = Lattice::sheet(top_left, 16, 12, 2.2 * radius);
+            let cloth = _model.solver.add_soft_body(&lattice, &body);
+            for ball in cloth.iter().take(16) {
+                _model.solver.pin_to_point(*ball);
+            }
+        }
+        Key::U => {
+            // Drops a rigid grain at the mouse, a different shape each time
+            let center = _model.camera.to_world(_app.mouse.position());
+            let radius = _model.brush.ball_radius;
+            _model.solver.add_cluster(
+                center,
+                0.,
+                &_model.cluster_shape.offsets(radius),
+                radius,
+                Hsv::from(_model.brush.color),
+            );
+            _model.cluster_shape = _model.cluster_shape.next();
+        }
+        Key::Q => {
+            // A paddle circling the mouse at the brush radius, or sweeping across it with
+            // shift held
+            let mouse = _model.camera.to_world(_app.mouse.position());
+            let reach = _model.brush.radius;
+            let path = if _app.keys.mods.shift() {
+                Path::Line {
+                    from: mouse - Vec2::new(reach, 0.),
+                    to: mouse + Vec2::new(reach, 0.),
+                    period: 120.,
+                }
+            } else {
+                Path::Circle {
+                    center: mouse,
+                    radius: reach,
+                    speed: 0.05,
+                    phase: 0.,
+                }
+            };
+            let radius = 2. * _model.brush.ball_radius;
+            let color = Hsv::from(_model.brush.color);
+            _model.solver.add_kinematic(path, radius, color);
+        }
+        Key::I => {
+            // A Galton board of pegs hanging below the mouse
+            let top = _model.camera.to_world(_app.mouse.position());
+            let radius = _model.brush.ball_radius;
+            let color = Hsv::from(_model.brush.color);
+            _model
+                .solver
+                .add_galton_pegs(top, 12, 6. * radius, radius, color);
+        }
+        // Used from the next bake on
+        Key::A => {
+            _model.solver.image_sampling = _model.solver.image_sampling.next();
+            eprintln!("Image sampling: {:?}", _model.solver.image_sampling);
+        }
+        // Quantises to the colour mode palette, or stops quantising
+        Key::K => {
+            _model.quantize_name = match _model.quantize_name {
+                Some(_) => None,
+                None => Some(_model.palette_name.clone().unwrap_or("viridis".to_string())),
+            };
+            load_quantize(_model);
+            eprintln!(
+                "Quantize: {}",
+                _model.quantize_name.as_deref().unwrap_or("none")
+            );
+        }
+        Key::X => {
+            _model.solver.dither = !_model.solver.dither;
+            eprintln!("Dither: {}", _model.solver.dither);
+        }
+        Key::C => {
+            let mode = _model.solver.color_mode.next();
+            _model.solver.set_color_mode(mode);
+        }
+        Key::E => {
+            let path = format!("frame_{}.svg", _app.elapsed_frames());
+            let options = SvgOptions::new(_model.world);
+            match write_svg(&path, &_model.solver, &options) {
+                Ok(()) => eprintln!("Wrote {}", path),
+                Err(e) => eprintln!("Could not write {}: {}", path, e),
+            }
+        }
+        Key::F => _model.camera.fit(&_model.solver, _app.window_rect()),
+        Key::T => {
+            let mouse = _model.camera.to_world(_app.mouse.position());
+            _model.camera.toggle_follow(&_model.solver, mouse, 50.);
+        }
+        Key::G => _model.overlays.grid = !_model.overlays.grid,
+        Key::V => _model.overlays.velocity = !_model.overlays.velocity,
+        Key::N => {
+            _model.overlays.contacts = !_model.overlays.contacts;
+            sync_contacts(_model);
+        }
+        Key::B => _model.overlays.boundary_normals = !_model.overlays.boundary_normals,
+        Key::H => _model.overlays.heatmap = !_model.overlays.heatmap,
+        Key::S => {
+            _model.show_stats = !_model.show_stats;
+            sync_contacts(_model);
+        }
+        Key::R => match _model.recorder.take() {
+            Some(recorder) => match recorder.finish() {
+                Ok(()) => eprintln!("Recording finished"),
+                Err(e) => eprintln!("Could not finish recording: {}", e),
+            },
+            None => {
+                let path = format!("run_{}.gif", _app.elapsed_frames());
+                let options = RecordOptions {
+                    sim_rate: _model.clock.rate,
+                    ..RecordOptions::new(_model.world)
+                };
+                match Recorder::gif(&path, options) {
+                    Ok(recorder) => {
+                        eprintln!("Recording {}", path);
+                        _model.recorder = Some(recorder);
+                    }
+                    Err(e) => eprintln!("Could not record {}: {}", path, e),
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+fn resized(_app: &App, _model: &mut Model, size: Vec2) {
+    _model.camera.resize(Rect::from_wh(size), _model.world);
+}
+
+fn mouse_wheel(_app: &App, _model: &mut Model, delta: MouseScrollDelta, _phase: TouchPhase) {
+    let lines = match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 20.,
+    };
+    _model
+        .camera
+        .zoom_at(_app.mouse.position(), 1.1f32.powf(lines));
+}
+
+// Left clicks belong to the scene, right or middle drags pan the camera
+fn mouse_pressed(_app: &App, _model: &mut Model, button: MouseButton) {
+    match button {
+        MouseButton::Right | MouseButton::Middle => _model.camera.start_drag(_app.mouse.position()),
+        MouseButton::Left if _model.editor.enabled => {
+            let world = _model.camera.to_world(_app.mouse.position());
+            _model
+                .editor
+                .mouse_pressed(&mut _model.solver, world, _model.camera.scale());
+        }
+        MouseButton::Left => {
+            let world = _model.camera.to_world(_app.mouse.position());
+            _model.brush.press(&_model.solver, world);
+        }
+        _ => {}
+    }
+}
+
+fn mouse_released(_app: &App, _model: &mut Model, button: MouseButton) {
+    match button {
+        MouseButton::Right | MouseButton::Middle => _model.camera.end_drag(),
+        MouseButton::Left => {
+            _model.editor.mouse_released();
+            _model.brush.release();
+        }
+        _ => {}
+    }
+}
 
-    // Timing
-    let time_ran = now.elapsed();
-    let target_time = std::time::Duration::from_millis(16);
-    if time_ran < target_time {
-        std::thread::sleep(target_time - time_ran);
+fn mouse_moved(_app: &App, _model: &mut Model, pos: Point2) {
+    _model.camera.drag(pos);
+    if _model.editor.enabled {
+        let world = _model.camera.to_world(pos);
+        _model.editor.mouse_moved(&mut _model.solver, world);
     }
-    _model.fps = 1. / now.elapsed().as_secs_f32();
 }
 
 fn view(_app: &App, _model: &Model, frame: Frame) {
+    let now = Instant::now();
     let draw = _app.draw();
     frame.clear(BLACK);
 
-    _model.solver.draw(&draw);
+    let world = _model.camera.transform(&draw);
+    _model.solver.draw(&world);
+    _model.overlays.draw(&_model.solver, &world);
+    if _model.editor.enabled {
+        _model
+            .editor
+            .draw(&_model.solver, &world, _model.camera.scale());
+    } else {
+        let mouse = _model.camera.to_world(_app.mouse.position());
+        _model.brush.draw(&world, mouse, _model.camera.scale());
+    }
 
-    draw.text(format!("FPS {:.0} Ball Count {}", _app.fps(), _model.ball_count).as_str())
-        .font_size(30)
-        .width(800.)
-        .xy(Vec2::new(-300., 460.));
+    // HUD in window coordinates, pinned to the top left corner
+    let window = _app.window_rect();
+    draw.text(
+        format!(
+            "FPS {:.0} Ball Count {} Sim {}",
+            _app.fps(),
+            _model.ball_count,
+            _model.clock.describe()
+        )
+        .as_str(),
+    )
+    .font_size(30)
+    .left_justify()
+    .w_h(800., 36.)
+    .x_y(window.left() + 410., window.top() - 20.);
+    let tool = if _model.editor.enabled {
+        _model.editor.describe()
+    } else {
+        _model.brush.describe()
+    };
+    let status = format!(
+        "{} {} {}",
+        tool,
+        _model.overlays.describe(),
+        sensor::describe_sensors(&_model.solver.sensors, &_model.solver.sensor_events)
+    );
+    draw.text(&status)
+        .font_size(16)
+        .left_justify()
+        .w_h(800., 20.)
+        .x_y(window.left() + 410., window.top() - 50.);
+    if _model.show_stats {
+        let panel = Rect::from_corners(
+            pt2(window.left() + 10., window.top() - 80.),
+            pt2(window.left() + 270., window.bottom() + 10.),
+        );
+        _model.stats.draw(&draw, panel);
+    }
+
+    if _model.params.visible {
+        let panel = Rect::from_corners(
+            pt2(window.right() - 330., window.top() - 80.),
+            pt2(window.right() - 10., window.top() - 240.),
+        );
+        let params = Params::read(&_model.solver, _model.timestep, &_model.spawners);
+        _model.params.draw(&draw, &params, panel);
+    }
 
     draw.to_frame(_app, &frame).unwrap();
+    _model.draw_ms.set(now.elapsed().as_secs_f32() * 1000.);
 }
@@ -0,0 +1,109 @@
+use nannou::prelude::*;
+
+// Where a kinematic ball is at each moment. Times are in solver steps, see `Solver::time`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Path {
+    // Round a circle at `speed` radians per step, starting `phase` radians round
+    Circle {
+        center: Vec2,
+        radius: f32,
+        speed: f32,
+        phase: f32,
+    },
+    // From `from` to `to` and back once every `period` steps
+    Line {
+        from: Vec2,
+        to: Vec2,
+        period: f32,
+    },
+}
+
+impl Path {
+    pub fn at(&self, time: f32) -> Vec2 {
+        match self {
+            Path::Circle {
+                center,
+                radius,
+                speed,
+                phase,
+            } => *center + Vec2::new(*radius, 0.).rotate(phase + speed * time),
+            Path::Line { from, to, period } => {
+                // Eases in and out at the ends
+                let t = 0.5 - 0.5 * (2. * PI * time / period).cos();
+                from.lerp(*to, t)
+            }
+        }
+    }
+}
+
+// How a ball moves. Only dynamic balls are moved by forces, boundaries and collisions;
+// the others push dynamic balls aside as if they had infinite mass.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Motion {
+    Dynamic,
+    // Stays where it is, like a peg
+    Fixed,
+    // Follows a path
+    Kinematic(Path),
+}
+
+impl Motion {
+    // Value of `motion=` in a ball's scene line, see `parse_motion`
+    pub fn describe(&self) -> String {
+        match self {
+            Motion::Dynamic => "dynamic".to_string(),
+            Motion::Fixed => "fixed".to_string(),
+            Motion::Kinematic(Path::Circle {
+                center,
+                radius,
+                speed,
+                phase,
+            }) => format!(
+                "circle:{},{},{},{},{}",
+                center.x, center.y, radius, speed, phase
+            ),
+            Motion::Kinematic(Path::Line { from, to, period }) => {
+                format!("line:{},{},{},{},{}", from.x, from.y, to.x, to.y, period)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circle_path() {
+        let path = Path::Circle {
+            center: Vec2::new(5., -5.),
+            radius: 10.,
+            speed: PI / 8.,
+            phase: PI / 2.,
+        };
+        assert!(path.at(0.).abs_diff_eq(Vec2::new(5., 5.), 1e-4));
+        assert!(path.at(4.).abs_diff_eq(Vec2::new(-5., -5.), 1e-4));
+        assert!(path.at(16.).abs_diff_eq(path.at(0.), 1e-4));
+        for step in 0..20 {
+            let dist = path.at(step as f32 * 0.3).distance(Vec2::new(5., -5.));
+            assert!((dist - 10.).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn line_path_goes_there_and_back() {
+        let (from, to) = (Vec2::new(-10., 0.), Vec2::new(30., 20.));
+        let path = Path::Line {
+            from,
+            to,
+            period: 60.,
+        };
+        assert!(path.at(0.).abs_diff_eq(from, 1e-4));
+        assert!(path.at(15.).abs_diff_eq(from.lerp(to, 0.5), 1e-4));
+        assert!(path.at(30.).abs_diff_eq(to, 1e-4));
+        assert!(path.at(45.).abs_diff_eq(path.at(15.), 1e-4));
+        assert!(path.at(60.).abs_diff_eq(from, 1e-4));
+        // Eased, so it starts slowly
+        assert!(path.at(1.).distance(from) < path.at(16.).distance(path.at(15.)));
+    }
+}
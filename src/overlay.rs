@@ -0,0 +1,135 @@
+use crate::ball::*;
+use crate::boundary::*;
+use crate::contact::*;
+use crate::palette::*;
+use crate::solver::*;
+use nannou::prelude::*;
+
+// Arrow length per unit of per-substep velocity
+const VELOCITY_SCALE: f32 = 20.;
+const NORMAL_LENGTH: f32 = 12.;
+// Spacing between boundary normal ticks along an outline
+const NORMAL_SPACING: f32 = 30.;
+
+// Debug layers drawn over the scene. They only read the solver, apart from
+// `sync_contacts` in main.rs turning on contact recording when contact normals are shown.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Overlays {
+    pub grid: bool,
+    pub velocity: bool,
+    pub contacts: bool,
+    pub boundary_normals: bool,
+    pub heatmap: bool,
+}
+
+impl Overlays {
+    pub fn describe(&self) -> String {
+        let names = [
+            (self.grid, "grid"),
+            (self.velocity, "velocity"),
+            (self.contacts, "contacts"),
+            (self.boundary_normals, "normals"),
+            (self.heatmap, "heatmap"),
+        ];
+        names
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn draw(&self, solver: &Solver, draw: &Draw) {
+        if self.heatmap {
+            solver.hash.draw_heatmap(draw, &Palette::magma());
+        }
+        if self.grid {
+            solver.hash.draw(draw);
+        }
+        if self.boundary_normals {
+            draw_boundary_normals(solver, draw);
+        }
+        if self.velocity {
+            for ball in solver.balls.iter() {
+                let ball = ball.borrow();
+                let vel = ball.pos - ball.prev_pos;
+                if vel.length_squared() > 1e-6 {
+                    draw.arrow()
+                        .start(ball.pos)
+                        .end(ball.pos + vel * VELOCITY_SCALE)
+                        .weight(1.)
+                        .head_length(3.)
+                        .head_width(2.)
+                        .color(WHITE);
+                }
+            }
+        }
+        if let (true, Some(log)) = (self.contacts, &solver.contacts) {
+            draw_contacts(solver, log, draw);
+        }
+    }
+}
+
+fn draw_contacts(solver: &Solver, log: &ContactLog, draw: &Draw) {
+    for contact in log.ball_contacts.iter() {
+        if let (Some(a), Some(b)) = (solver.balls.get(contact.a), solver.balls.get(contact.b)) {
+            let (a, b) = (a.borrow(), b.borrow());
+            let normal = (a.pos - b.pos).normalize_or_zero();
+            let point = b.pos + normal * b.radius;
+            draw.line()
+                .start(point - normal * NORMAL_LENGTH / 2.)
+                .end(point + normal * NORMAL_LENGTH / 2.)
+                .weight(1.)
+                .color(YELLOW);
+        }
+    }
+    for contact in log.boundary_contacts.iter() {
+        if let Some(ball) = solver.balls.get(contact.ball) {
+            let ball = ball.borrow();
+            let point = ball.pos - contact.normal * ball.radius;
+            draw.line()
+                .start(point)
+                .end(point + contact.normal * NORMAL_LENGTH)
+                .weight(1.)
+                .color(ORANGE);
+        }
+    }
+}
+
+// Ticks along each outline pointing to the side balls are kept on
+fn draw_boundary_normals(solver: &Solver, draw: &Draw) {
+    for bound in solver.boundaries.iter() {
+        for line in bound.outline() {
+            let mut carried = 0.;
+            for pair in line.windows(2) {
+                let segment = pair[1] - pair[0];
+                let length = segment.length();
+                if length < 1e-6 {
+                    continue;
+                }
+                let mut normal = segment.perp() / length;
+                let mut t = NORMAL_SPACING - carried;
+                while t < length {
+                    let point = pair[0] + segment * (t / length);
+                    // A tiny probe ball in front of the wall should be left alone
+                    let probe = point + normal * 2.;
+                    let probe = Ball::probe(probe, 0.5);
+                    let blocked = match bound.kind() {
+                        BoundaryType::Inner => bound.detect_inner_collision(&probe),
+                        BoundaryType::Outer => bound.detect_outer_collision(&probe),
+                    };
+                    if blocked {
+                        normal = -normal;
+                    }
+                    draw.line()
+                        .start(point)
+                        .end(point + normal * NORMAL_LENGTH)
+                        .weight(1.)
+                        .color(CYAN);
+                    t += NORMAL_SPACING;
+                }
+                carried = (carried + length) % NORMAL_SPACING;
+            }
+        }
+    }
+}
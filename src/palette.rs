@@ -0,0 +1,229 @@
+use nannou::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Piecewise linear colour ramp over [0, 1]
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    pub stops: Vec<(f32, Rgba)>,
+}
+
+impl Gradient {
+    pub fn new(stops: Vec<(f32, Rgba)>) -> Self {
+        Gradient { stops }
+    }
+
+    // Evenly spaced stops
+    pub fn from_colors(colors: &[Rgba]) -> Self {
+        let last = (colors.len() - 1).max(1) as f32;
+        Gradient::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i as f32 / last, *c))
+                .collect(),
+        )
+    }
+
+    pub fn rainbow() -> Self {
+        Gradient::from_colors(
+            &(0..7)
+                .map(|i| Rgba::from(Hsv::new(i as f32 * 300. / 6., 1., 1.)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn sample(&self, t: f32) -> Rgba {
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+        let next = self
+            .stops
+            .iter()
+            .position(|(at, _)| *at >= t)
+            .unwrap_or(self.stops.len() - 1);
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        let (t0, c0) = self.stops[next - 1];
+        let (t1, c1) = self.stops[next];
+        let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1. };
+        Rgba::new(
+            c0.red + (c1.red - c0.red) * f,
+            c0.green + (c1.green - c0.green) * f,
+            c0.blue + (c1.blue - c0.blue) * f,
+            c0.alpha + (c1.alpha - c0.alpha) * f,
+        )
+    }
+}
+
+// Ten evenly spaced samples of the matplotlib colormaps, close enough together that
+// interpolating in sRGB keeps them perceptually uniform
+const VIRIDIS: [u32; 10] = [
+    0x440154, 0x482878, 0x3e4989, 0x31688e, 0x26828e, 0x1f9e89, 0x35b779, 0x6ece58, 0xb5de2b,
+    0xfde725,
+];
+const MAGMA: [u32; 10] = [
+    0x000004, 0x180f3d, 0x440f76, 0x721f81, 0x9e2f7f, 0xcd4071, 0xf1605d, 0xfd9668, 0xfeca8d,
+    0xfcfdbf,
+];
+const CIVIDIS: [u32; 10] = [
+    0x00224e, 0x123570, 0x3b496c, 0x575d6d, 0x707173, 0x8a8779, 0xa69d75, 0xc4b56c, 0xe4cf5b,
+    0xfee838,
+];
+
+pub fn hex_color(hex: u32) -> Rgba {
+    Rgba::new(
+        ((hex >> 16) & 0xff) as f32 / 255.,
+        ((hex >> 8) & 0xff) as f32 / 255.,
+        (hex & 0xff) as f32 / 255.,
+        1.,
+    )
+}
+
+// Anything that turns a scalar into a colour. Gradients blend between stops, discrete
+// palettes split [0, 1] into one equal bin per colour.
+#[derive(Debug, Clone)]
+pub enum Palette {
+    Gradient(Gradient),
+    Discrete(Vec<Rgba>),
+}
+
+impl Palette {
+    pub fn viridis() -> Self {
+        Palette::Gradient(Gradient::from_colors(&VIRIDIS.map(hex_color)))
+    }
+
+    pub fn magma() -> Self {
+        Palette::Gradient(Gradient::from_colors(&MAGMA.map(hex_color)))
+    }
+
+    pub fn cividis() -> Self {
+        Palette::Gradient(Gradient::from_colors(&CIVIDIS.map(hex_color)))
+    }
+
+    pub fn rainbow() -> Self {
+        Palette::Gradient(Gradient::rainbow())
+    }
+
+    // One of the built-in palettes by name, or else a palette file, see `load`
+    pub fn open(value: &str) -> io::Result<Self> {
+        match value {
+            "viridis" => Ok(Palette::viridis()),
+            "magma" => Ok(Palette::magma()),
+            "cividis" => Ok(Palette::cividis()),
+            "rainbow" => Ok(Palette::rainbow()),
+            path => Palette::load(path),
+        }
+    }
+
+    // Reads one colour per line as `rrggbb` or `#rrggbb`. Blank lines and lines starting
+    // with `//` are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut colors = vec![];
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let hex = line.trim_start_matches('#');
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid palette colour {:?}", line),
+                    )
+                })?;
+            colors.push(hex_color(value));
+        }
+        if colors.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "palette has no colours",
+            ));
+        }
+        Ok(Palette::Discrete(colors))
+    }
+
+    pub fn sample(&self, t: f32) -> Rgba {
+        match self {
+            Palette::Gradient(gradient) => gradient.sample(t),
+            Palette::Discrete(colors) => {
+                let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+                let bin = (t * colors.len() as f32) as usize;
+                colors[bin.min(colors.len() - 1)]
+            }
+        }
+    }
+
+    // Every colour the palette is built from, used as the target set when quantising
+    pub fn colors(&self) -> Vec<Rgba> {
+        match self {
+            Palette::Gradient(gradient) => gradient.stops.iter().map(|(_, c)| *c).collect(),
+            Palette::Discrete(colors) => colors.clone(),
+        }
+    }
+
+    // Maps `value` from `range` onto the palette
+    pub fn map(&self, value: f32, range: (f32, f32)) -> Rgba {
+        self.sample((value - range.0) / (range.1 - range.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn write_palette(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fast_circles_{}_{}.palette",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_skips_comments_and_blank_lines() {
+        let path = write_palette("valid", "// warm\n#ff0000\n\n  00ff00  \n0000FF\n");
+        let palette = Palette::load(&path);
+        fs::remove_file(&path).unwrap();
+        let colors = palette.unwrap().colors();
+        assert_eq!(colors.len(), 3);
+        assert_eq!(colors[0], hex_color(0xff0000));
+        assert_eq!(colors[1], hex_color(0x00ff00));
+        assert_eq!(colors[2], hex_color(0x0000ff));
+    }
+
+    #[test]
+    fn load_rejects_bad_colours() {
+        for (name, text) in [
+            ("short", "fff\n"),
+            ("not_hex", "#gg0000\n"),
+            ("empty", "// none\n"),
+        ] {
+            let path = write_palette(name, text);
+            let palette = Palette::load(&path);
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                palette.unwrap_err().kind(),
+                io::ErrorKind::InvalidData,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn discrete_palette_bins() {
+        let palette = Palette::Discrete(vec![hex_color(0x000000), hex_color(0xffffff)]);
+        assert_eq!(palette.sample(0.), hex_color(0x000000));
+        assert_eq!(palette.sample(0.49), hex_color(0x000000));
+        assert_eq!(palette.sample(0.5), hex_color(0xffffff));
+        assert_eq!(palette.sample(1.), hex_color(0xffffff));
+        assert_eq!(palette.sample(f32::NAN), hex_color(0x000000));
+    }
+}
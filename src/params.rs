@@ -0,0 +1,199 @@
+use crate::solver::*;
+use crate::spawn::*;
+use nannou::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    GravityX,
+    GravityY,
+    Substeps,
+    Timestep,
+    SpawnVelocity,
+    SpawnPeriod,
+    DetectMode,
+}
+
+pub const PARAMS: [Param; 7] = [
+    Param::GravityX,
+    Param::GravityY,
+    Param::Substeps,
+    Param::Timestep,
+    Param::SpawnVelocity,
+    Param::SpawnPeriod,
+    Param::DetectMode,
+];
+
+impl Param {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Param::GravityX => "gravity x",
+            Param::GravityY => "gravity y",
+            Param::Substeps => "substeps",
+            Param::Timestep => "timestep",
+            Param::SpawnVelocity => "spawn velocity",
+            Param::SpawnPeriod => "spawn period",
+            Param::DetectMode => "detect mode",
+        }
+    }
+}
+
+// The live-tunable settings, gathered from wherever they are stored. Spawner
+// settings are read from the first spawner and written to all of them.
+#[derive(Debug, Clone, Copy)]
+pub struct Params {
+    pub gravity: Vec2,
+    pub substeps: usize,
+    pub timestep: f32,
+    pub spawn_velocity: f32,
+    pub spawn_period: usize,
+    pub detect_mode: DetectMode,
+}
+
+impl Params {
+    pub fn read<S: Spawner>(solver: &Solver, timestep: f32, spawners: &[S]) -> Self {
+        Params {
+            gravity: solver.gravity,
+            substeps: solver.substeps,
+            timestep,
+            spawn_velocity: spawners.first().map_or(0., |s| s.spawn_velocity()),
+            spawn_period: spawners.first().map_or(1, |s| s.spawn_period()),
+            detect_mode: solver.detect_mode,
+        }
+    }
+
+    pub fn apply<S: Spawner>(&self, solver: &mut Solver, timestep: &mut f32, spawners: &mut [S]) {
+        solver.gravity = self.gravity;
+        solver.substeps = self.substeps;
+        solver.detect_mode = self.detect_mode;
+        *timestep = self.timestep;
+        for spawner in spawners.iter_mut() {
+            spawner.set_spawn_velocity(self.spawn_velocity);
+            spawner.set_spawn_period(self.spawn_period);
+        }
+    }
+
+    pub fn value(&self, param: Param) -> String {
+        match param {
+            Param::GravityX => format!("{:.4e}", self.gravity.x),
+            Param::GravityY => format!("{:.4e}", self.gravity.y),
+            Param::Substeps => format!("{}", self.substeps),
+            Param::Timestep => format!("{:.4e}", self.timestep),
+            Param::SpawnVelocity => format!("{:.3}", self.spawn_velocity),
+            Param::SpawnPeriod => format!("{}", self.spawn_period),
+            Param::DetectMode => format!("{:?}", self.detect_mode),
+        }
+    }
+
+    // Gravity steps are a tenth of the current value, or enough to move a ball by a
+    // hundredth of a unit per substep when gravity is off
+    fn gravity_step(&self) -> f32 {
+        let subdt = self.timestep / self.substeps as f32;
+        (0.1 * self.gravity.abs().max_element()).max(0.01 / (subdt * subdt))
+    }
+
+    // Moves a setting one step up or down
+    pub fn adjust(&mut self, param: Param, up: bool) {
+        let sign = if up { 1. } else { -1. };
+        let scale = if up { 1.1 } else { 1. / 1.1 };
+        match param {
+            Param::GravityX => self.gravity.x += sign * self.gravity_step(),
+            Param::GravityY => self.gravity.y += sign * self.gravity_step(),
+            Param::Substeps => {
+                self.substeps = if up {
+                    self.substeps + 1
+                } else {
+                    self.substeps.saturating_sub(1).max(1)
+                }
+            }
+            Param::Timestep => self.timestep *= scale,
+            Param::SpawnVelocity => self.spawn_velocity += sign * 0.1,
+            Param::SpawnPeriod => {
+                self.spawn_period = if up {
+                    self.spawn_period + 1
+                } else {
+                    self.spawn_period.saturating_sub(1).max(1)
+                }
+            }
+            Param::DetectMode => {
+                self.detect_mode = match self.detect_mode {
+                    DetectMode::SpatialPartition => DetectMode::Slow,
+                    DetectMode::Slow => DetectMode::SpatialPartition,
+                }
+            }
+        }
+    }
+
+    pub fn reset(&mut self, param: Param, defaults: &Params) {
+        match param {
+            Param::GravityX => self.gravity.x = defaults.gravity.x,
+            Param::GravityY => self.gravity.y = defaults.gravity.y,
+            Param::Substeps => self.substeps = defaults.substeps,
+            Param::Timestep => self.timestep = defaults.timestep,
+            Param::SpawnVelocity => self.spawn_velocity = defaults.spawn_velocity,
+            Param::SpawnPeriod => self.spawn_period = defaults.spawn_period,
+            Param::DetectMode => self.detect_mode = defaults.detect_mode,
+        }
+    }
+}
+
+// Keyboard-driven list of `Params`: up and down pick a setting, left and right change
+// it, 0 resets it and Home resets everything
+#[derive(Default)]
+pub struct ParamPanel {
+    pub visible: bool,
+    pub selected: usize,
+    // Values the scene was built with, captured once it exists
+    pub defaults: Option<Params>,
+}
+
+impl ParamPanel {
+    // Returns whether the key was used by the panel
+    pub fn key_pressed(&mut self, key: Key, params: &mut Params) -> bool {
+        let param = PARAMS[self.selected];
+        match key {
+            Key::Up => self.selected = (self.selected + PARAMS.len() - 1) % PARAMS.len(),
+            Key::Down => self.selected = (self.selected + 1) % PARAMS.len(),
+            Key::Left => params.adjust(param, false),
+            Key::Right => params.adjust(param, true),
+            Key::Key0 => {
+                if let Some(defaults) = &self.defaults {
+                    params.reset(param, defaults);
+                }
+            }
+            Key::Home => {
+                if let Some(defaults) = self.defaults {
+                    *params = defaults;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Lays the list out downwards from the top left corner of `rect`
+    pub fn draw(&self, draw: &Draw, params: &Params, rect: Rect) {
+        let line_height = 18.;
+        let mut y = rect.top() - line_height / 2.;
+        let mut lines = vec![(
+            "params (arrows edit, 0 reset, Home reset all, W save)".to_string(),
+            GRAY,
+        )];
+        lines.extend(PARAMS.iter().enumerate().map(|(i, param)| {
+            let marker = if i == self.selected { ">" } else { " " };
+            let color = if i == self.selected { YELLOW } else { WHITE };
+            (
+                format!("{} {:<16}{}", marker, param.name(), params.value(*param)),
+                color,
+            )
+        }));
+        for (text, color) in lines {
+            draw.text(&text)
+                .font_size(14)
+                .left_justify()
+                .w_h(rect.w(), line_height)
+                .x_y(rect.x(), y)
+                .color(color);
+            y -= line_height;
+        }
+    }
+}
@@ -1,71 +1,250 @@
+use crate::palette::*;
 use grid::*;
 use nannou::prelude::*;
 
+// Axes along which the world wraps around, so balls leaving one side come back on
+// the other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Periodic {
+    pub x: bool,
+    pub y: bool,
+}
+
+impl Periodic {
+    pub fn name(&self) -> &'static str {
+        match (self.x, self.y) {
+            (false, false) => "none",
+            (true, false) => "x",
+            (false, true) => "y",
+            (true, true) => "xy",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "none" => Ok(Periodic { x: false, y: false }),
+            "x" => Ok(Periodic { x: true, y: false }),
+            "y" => Ok(Periodic { x: false, y: true }),
+            "xy" => Ok(Periodic { x: true, y: true }),
+            _ => Err(format!("periodic={} is not none, x, y or xy", value)),
+        }
+    }
+}
+
 pub struct SpatialHash {
     pub grid: Grid<Vec<usize>>,
     pub resolution: f32,
-    win_width: f32,
-    win_height: f32,
+    // Size of a cell. Along a periodic axis the cells are stretched to divide the
+    // world exactly, so the cells either side of the seam are true neighbours.
+    pub cell: Vec2,
+    // World rectangle the grid covers, balls outside it are hashed into the edge cells
+    pub bounds: Rect,
+    pub periodic: Periodic,
 }
 
 impl SpatialHash {
-    pub fn new(radius: f32, win_width: f32, win_height: f32) -> Self {
+    pub fn new(radius: f32, bounds: Rect, periodic: Periodic) -> Self {
         let res = 2. * radius;
-        let nrow = (win_height / res) as usize + 1;
-        let ncol = (win_width / res) as usize + 1;
-        let grid = Grid::new(ncol, nrow);
+        let cells = |extent: f32, wraps: bool| {
+            if wraps {
+                ((extent / res) as usize).max(1)
+            } else {
+                (extent / res) as usize + 1
+            }
+        };
+        let nrow = cells(bounds.h(), periodic.y);
+        let ncol = cells(bounds.w(), periodic.x);
+        let cell = Vec2::new(
+            if periodic.x {
+                bounds.w() / ncol as f32
+            } else {
+                res
+            },
+            if periodic.y {
+                bounds.h() / nrow as f32
+            } else {
+                res
+            },
+        );
+        let grid = Grid::new(nrow, ncol);
         SpatialHash {
             grid,
             resolution: res,
-            win_height,
-            win_width,
+            cell,
+            bounds,
+            periodic,
         }
     }
 
+    // Same resolution and bounds, wrapping along different axes
+    pub fn with_periodic(&self, periodic: Periodic) -> Self {
+        SpatialHash::new(self.resolution / 2., self.bounds, periodic)
+    }
+
+    // Displacement that brings `pos` back inside the world along the periodic axes
+    pub fn wrap_offset(&self, pos: Vec2) -> Vec2 {
+        let wrap =
+            |value: f32, low: f32, extent: f32| low + (value - low).rem_euclid(extent) - value;
+        Vec2::new(
+            if self.periodic.x {
+                wrap(pos.x, self.bounds.left(), self.bounds.w())
+            } else {
+                0.
+            },
+            if self.periodic.y {
+                wrap(pos.y, self.bounds.bottom(), self.bounds.h())
+            } else {
+                0.
+            },
+        )
+    }
+
+    // Displacement that moves `other` to its nearest periodic image as seen from
+    // `pos`, so a ghost of it can be collided with across the seam
+    pub fn ghost_offset(&self, pos: Vec2, other: Vec2) -> Vec2 {
+        let (w, h) = self.bounds.w_h();
+        Vec2::new(
+            if self.periodic.x {
+                w * ((pos.x - other.x) / w).round()
+            } else {
+                0.
+            },
+            if self.periodic.y {
+                h * ((pos.y - other.y) / h).round()
+            } else {
+                0.
+            },
+        )
+    }
+
+    // The cell `offset` rows and columns away, wrapping around periodic axes
+    pub fn neighbour(
+        &self,
+        row: usize,
+        col: usize,
+        offset: (isize, isize),
+    ) -> Option<(usize, usize)> {
+        let (rows, cols) = self.grid.size();
+        let step = |index: usize, by: isize, count: usize, wraps: bool| {
+            let index = index as isize + by;
+            let count = count as isize;
+            if (0..count).contains(&index) || (wraps && count > 0) {
+                Some(index.rem_euclid(count) as usize)
+            } else {
+                None
+            }
+        };
+        Some((
+            step(row, offset.0, rows, self.periodic.y)?,
+            step(col, offset.1, cols, self.periodic.x)?,
+        ))
+    }
+
     pub fn hash(&mut self, pos: Vec2, index: usize) {
         // println!("{:?}", pos);
+        let pos = pos + self.wrap_offset(pos);
         let mut px = 0.;
         let mut py = 0.;
-        py = ((self.win_height / 2.0) - pos.y) / self.resolution;
-        px = ((self.win_width / 2.0) + pos.x) / self.resolution;
+        py = (self.bounds.top() - pos.y) / self.cell.y;
+        px = (pos.x - self.bounds.left()) / self.cell.x;
         // println!("{} {}", px, py);
-        let list = self.grid.get_mut(py as usize, px as usize);
+        // Balls outside the grid go in the nearest edge cell, the float casts saturating
+        // at zero on the low side
+        let (rows, cols) = self.grid.size();
+        let list = self
+            .grid
+            .get_mut((py as usize).min(rows - 1), (px as usize).min(cols - 1));
         if list.is_some() {
             list.unwrap().push(index);
         }
     }
 
+    // World rectangle covered by a cell
+    pub fn cell_rect(&self, row: usize, col: usize) -> Rect {
+        Rect::from_x_y_w_h(
+            self.bounds.left() + (col as f32 + 0.5) * self.cell.x,
+            self.bounds.top() - (row as f32 + 0.5) * self.cell.y,
+            self.cell.x,
+            self.cell.y,
+        )
+    }
+
     pub fn draw(&self, draw: &Draw) {
-        // Detect collisions
         let (rows, cols) = self.grid.size();
-        // println!("{} {}", rows, cols);
         for cr in 0..rows {
             for cc in 0..cols {
-                if (self.grid.get(cr, cc).is_some()) {
-                    let items: &Vec<usize> = self.grid.get(cr, cc).unwrap();
-                    if !items.is_empty() {
-                        draw.rect()
-                            .xy(Vec2::new(
-                                (cc as f32 * self.resolution) - self.win_width / 2.0,
-                                (-1. * cr as f32 * self.resolution) + self.win_height / 2.0,
-                            ))
-                            .wh(Vec2::new(self.resolution, self.resolution))
-                            .stroke(WHITE)
-                            .stroke_weight(0.5)
-                            .rgba(1., 0., 0., 0.1);
-                    } else {
-                        draw.rect()
-                            .xy(Vec2::new(
-                                (cc as f32 * self.resolution) - self.win_width / 2.0,
-                                (-1. * cr as f32 * self.resolution) + self.win_height / 2.0,
-                            ))
-                            .wh(Vec2::new(self.resolution, self.resolution))
-                            .stroke(WHITE)
-                            .stroke_weight(0.5)
-                            .no_fill();
-                    }
+                let rect = self.cell_rect(cr, cc);
+                let cell = draw
+                    .rect()
+                    .xy(rect.xy())
+                    .wh(rect.wh())
+                    .stroke(WHITE)
+                    .stroke_weight(0.5);
+                if self.grid[cr][cc].is_empty() {
+                    cell.no_fill();
+                } else {
+                    cell.rgba(1., 0., 0., 0.1);
+                }
+            }
+        }
+    }
+
+    // Fills occupied cells by how many balls were hashed into them
+    pub fn draw_heatmap(&self, draw: &Draw, palette: &Palette) {
+        let max = self.grid.iter().map(|cell| cell.len()).max().unwrap_or(0);
+        let (rows, cols) = self.grid.size();
+        for cr in 0..rows {
+            for cc in 0..cols {
+                let count = self.grid[cr][cc].len();
+                if count > 0 {
+                    let rect = self.cell_rect(cr, cc);
+                    let mut color = palette.map(count as f32, (0., max as f32));
+                    color.alpha = 0.6;
+                    draw.rect().xy(rect.xy()).wh(rect.wh()).color(color);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_parse_round_trips_names() {
+        for name in ["none", "x", "y", "xy"] {
+            assert_eq!(Periodic::parse(name).unwrap().name(), name);
+        }
+        assert_eq!(
+            Periodic::parse("y").unwrap(),
+            Periodic { x: false, y: true }
+        );
+        assert!(Periodic::parse("yx").is_err());
+        assert!(Periodic::parse("").is_err());
+    }
+
+    #[test]
+    fn neighbours_wrap_only_along_periodic_axes() {
+        let bounds = Rect::from_w_h(100., 100.);
+        let walled = SpatialHash::new(10., bounds, Periodic::default());
+        assert_eq!(walled.neighbour(0, 0, (-1, 0)), None);
+        assert_eq!(walled.neighbour(0, 0, (1, 1)), Some((1, 1)));
+
+        let wrapped = SpatialHash::new(10., bounds, Periodic { x: true, y: false });
+        let (_, cols) = wrapped.grid.size();
+        assert_eq!(wrapped.neighbour(0, 0, (0, -1)), Some((0, cols - 1)));
+        assert_eq!(wrapped.neighbour(0, cols - 1, (0, 1)), Some((0, 0)));
+        assert_eq!(wrapped.neighbour(0, 0, (-1, -1)), None);
+    }
+
+    #[test]
+    fn wrap_offset_brings_positions_back_inside() {
+        let bounds = Rect::from_w_h(100., 80.);
+        let hash = SpatialHash::new(10., bounds, Periodic { x: true, y: true });
+        let pos = Vec2::new(60., -45.);
+        let wrapped = pos + hash.wrap_offset(pos);
+        assert!(wrapped.abs_diff_eq(Vec2::new(-40., 35.), 1e-4));
+        assert_eq!(hash.wrap_offset(Vec2::new(10., 10.)), Vec2::ZERO);
+    }
+}
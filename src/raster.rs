@@ -0,0 +1,124 @@
+use crate::solver::*;
+use nannou::image::{Rgba as Pixel, RgbaImage};
+use nannou::prelude::*;
+
+// Software rendering of a frame, so recordings don't depend on a window
+pub struct RasterOptions {
+    // World rectangle that fills the image
+    pub viewport: Rect,
+    // Image pixels per world unit
+    pub scale: f32,
+    pub background: Rgba,
+    pub boundary_color: Option<Rgba>,
+}
+
+impl RasterOptions {
+    // Default styling, one pixel per world unit across `viewport`
+    pub fn new(viewport: Rect) -> Self {
+        RasterOptions {
+            viewport,
+            scale: 1.,
+            background: Rgba::new(0., 0., 0., 1.),
+            boundary_color: Some(Rgba::new(1., 1., 1., 1.)),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (
+            (self.viewport.w() * self.scale).ceil().max(1.) as u32,
+            (self.viewport.h() * self.scale).ceil().max(1.) as u32,
+        )
+    }
+
+    // Continuous pixel coordinates of a world point, y pointing down
+    fn to_pixel(&self, p: Vec2) -> Vec2 {
+        Vec2::new(
+            (p.x - self.viewport.left()) * self.scale,
+            (self.viewport.top() - p.y) * self.scale,
+        )
+    }
+}
+
+fn to_pixel_color(color: Rgba) -> [f32; 3] {
+    [color.red, color.green, color.blue]
+}
+
+// Blends `color` over the pixel with the given coverage
+fn blend(image: &mut RgbaImage, x: i64, y: i64, color: [f32; 3], coverage: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    for c in 0..3 {
+        let old = pixel[c] as f32 / 255.;
+        let new = old + (color[c] - old) * coverage;
+        pixel[c] = (new.clamp(0., 1.) * 255.).round() as u8;
+    }
+    pixel[3] = 255;
+}
+
+fn fill_disc(image: &mut RgbaImage, centre: Vec2, radius: f32, color: [f32; 3]) {
+    let x0 = (centre.x - radius - 1.).floor() as i64;
+    let x1 = (centre.x + radius + 1.).ceil() as i64;
+    let y0 = (centre.y - radius - 1.).floor() as i64;
+    let y1 = (centre.y + radius + 1.).ceil() as i64;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let d = (Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - centre).length();
+            // One pixel wide antialiased edge
+            let coverage = (radius - d + 0.5).clamp(0., 1.);
+            if coverage > 0. {
+                blend(image, x, y, color, coverage);
+            }
+        }
+    }
+}
+
+fn draw_line(image: &mut RgbaImage, a: Vec2, b: Vec2, color: [f32; 3]) {
+    let steps = (b - a).length().ceil().max(1.) as usize;
+    for i in 0..=steps {
+        let p = a.lerp(b, i as f32 / steps as f32);
+        blend(image, p.x as i64, p.y as i64, color, 1.);
+    }
+}
+
+pub fn render(solver: &Solver, options: &RasterOptions) -> RgbaImage {
+    let (width, height) = options.size();
+    let background = options.background;
+    let mut image = RgbaImage::from_pixel(
+        width,
+        height,
+        Pixel([
+            (background.red * 255.) as u8,
+            (background.green * 255.) as u8,
+            (background.blue * 255.) as u8,
+            255,
+        ]),
+    );
+
+    for ball in solver.balls.iter() {
+        let ball = ball.borrow();
+        fill_disc(
+            &mut image,
+            options.to_pixel(ball.pos),
+            ball.radius * options.scale,
+            to_pixel_color(Rgba::from(ball.color)),
+        );
+    }
+
+    if let Some(color) = options.boundary_color {
+        for bound in solver.boundaries.iter() {
+            for line in bound.outline() {
+                for pair in line.windows(2) {
+                    draw_line(
+                        &mut image,
+                        options.to_pixel(pair[0]),
+                        options.to_pixel(pair[1]),
+                        to_pixel_color(color),
+                    );
+                }
+            }
+        }
+    }
+    image
+}
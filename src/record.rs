@@ -0,0 +1,145 @@
+use crate::raster::*;
+use crate::solver::*;
+use nannou::image::codecs::gif::{GifEncoder, Repeat};
+use nannou::image::{Delay, Frame, RgbaImage};
+use nannou::prelude::Rect;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+// Default simulation steps per second of simulated time
+pub const SIM_FPS: u32 = 60;
+
+pub struct RecordOptions {
+    pub fps: u32,
+    // Simulation steps per second, which sets how captures are spaced out
+    pub sim_rate: u32,
+    // Seconds of output to write before the recording stops itself
+    pub duration: Option<f32>,
+    pub raster: RasterOptions,
+}
+
+impl RecordOptions {
+    // Default rates and styling, recording `viewport`
+    pub fn new(viewport: Rect) -> Self {
+        RecordOptions {
+            fps: 30,
+            sim_rate: SIM_FPS,
+            duration: None,
+            raster: RasterOptions::new(viewport),
+        }
+    }
+}
+
+enum Encoder {
+    Gif(GifEncoder<BufWriter<File>>),
+    // Uncompressed 4:4:4 YUV, for piping into an external encoder
+    Y4m(BufWriter<Box<dyn Write>>),
+}
+
+pub struct Recorder {
+    encoder: Encoder,
+    options: RecordOptions,
+    sim_frames: usize,
+    written: usize,
+}
+
+fn to_io_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::other(e)
+}
+
+impl Recorder {
+    pub fn gif(path: &str, options: RecordOptions) -> io::Result<Self> {
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), 10);
+        encoder.set_repeat(Repeat::Infinite).map_err(to_io_error)?;
+        Ok(Recorder {
+            encoder: Encoder::Gif(encoder),
+            options,
+            sim_frames: 0,
+            written: 0,
+        })
+    }
+
+    // A path of `-` streams to stdout
+    pub fn y4m(path: &str, options: RecordOptions) -> io::Result<Self> {
+        let out: Box<dyn Write> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path)?)
+        };
+        let mut out = BufWriter::new(out);
+        let (width, height) = options.raster.size();
+        writeln!(
+            out,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444 XCOLORRANGE=FULL",
+            width, height, options.fps
+        )?;
+        Ok(Recorder {
+            encoder: Encoder::Y4m(out),
+            options,
+            sim_frames: 0,
+            written: 0,
+        })
+    }
+
+    pub fn finished(&self) -> bool {
+        self.options
+            .duration
+            .is_some_and(|d| self.written as f32 >= d * self.options.fps as f32)
+    }
+
+    // Call once per simulation step. Frame `n` is due once the simulation has reached
+    // `n * sim_rate / fps` steps, so rates that don't divide evenly keep their timing, and
+    // when the output rate is higher than the simulation rate a step is written more than
+    // once. Nothing is written once the duration is reached.
+    pub fn capture(&mut self, solver: &Solver) -> io::Result<()> {
+        let (fps, sim_rate) = (self.options.fps.max(1) as u64, self.options.sim_rate as u64);
+        let due = |written: usize| self.sim_frames as u64 * fps >= written as u64 * sim_rate;
+        let mut image = None;
+        while due(self.written) && !self.finished() {
+            let image = image
+                .get_or_insert_with(|| render(solver, &self.options.raster))
+                .clone();
+            match &mut self.encoder {
+                Encoder::Gif(encoder) => {
+                    let delay = Delay::from_numer_denom_ms(1000, self.options.fps.max(1));
+                    encoder
+                        .encode_frame(Frame::from_parts(image, 0, 0, delay))
+                        .map_err(to_io_error)?;
+                }
+                Encoder::Y4m(out) => write_y4m_frame(out, &image)?,
+            }
+            self.written += 1;
+        }
+        self.sim_frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.encoder {
+            // The GIF trailer is written when the encoder drops
+            Encoder::Gif(encoder) => {
+                drop(encoder);
+                Ok(())
+            }
+            Encoder::Y4m(mut out) => out.flush(),
+        }
+    }
+}
+
+// Full range BT.601, planar Y then U then V
+fn write_y4m_frame<W: Write>(out: &mut W, image: &RgbaImage) -> io::Result<()> {
+    let pixels = image.pixels().len();
+    let mut planes = vec![0u8; pixels * 3];
+    for (i, p) in image.pixels().enumerate() {
+        let (r, g, b) = (p[0] as f32, p[1] as f32, p[2] as f32);
+        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+        let u = 128. - 0.168736 * r - 0.331264 * g + 0.5 * b;
+        let v = 128. + 0.5 * r - 0.418688 * g - 0.081312 * b;
+        planes[i] = y.round().clamp(0., 255.) as u8;
+        planes[pixels + i] = u.round().clamp(0., 255.) as u8;
+        planes[2 * pixels + i] = v.round().clamp(0., 255.) as u8;
+    }
+    out.write_all(b"FRAME\n")?;
+    out.write_all(&planes)
+}
@@ -0,0 +1,353 @@
+use nannou::image::codecs::gif::GifDecoder;
+use nannou::image::io::Reader;
+use nannou::image::{AnimationDecoder, DynamicImage, ImageResult, RgbaImage};
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+
+// How the frames of an image sequence are revealed across simulation runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevealMode {
+    // One run per frame to bake every colormap, then the final pile cycles through them,
+    // advancing every `period` frames
+    Cycle { period: usize },
+    // Every run reveals the next frame, wrapping around
+    Sequential,
+}
+
+impl RevealMode {
+    // `cycle`, `cycle:PERIOD` or `sequential`, as written by `describe`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let error = || format!("reveal={} is not cycle, cycle:PERIOD or sequential", value);
+        match value.split_once(':') {
+            Some(("cycle", period)) => match period.parse() {
+                Ok(period) if period > 0 => Ok(RevealMode::Cycle { period }),
+                _ => Err(error()),
+            },
+            None if value == "cycle" => Ok(RevealMode::Cycle { period: 10 }),
+            None if value == "sequential" => Ok(RevealMode::Sequential),
+            _ => Err(error()),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            RevealMode::Cycle { period } => format!("cycle:{}", period),
+            RevealMode::Sequential => "sequential".to_string(),
+        }
+    }
+}
+
+// Loads an image sequence from an animated GIF, a directory of numbered images, or a
+// single still image
+pub fn load_frames<P: AsRef<Path>>(path: P) -> ImageResult<Vec<DynamicImage>> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let mut names: Vec<_> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| matches!(e.to_lowercase().as_str(), "png" | "jpg" | "jpeg"))
+            })
+            .collect();
+        // Shorter names first so unpadded numbering like 9.png, 10.png sorts correctly
+        names.sort_by_key(|p| (p.as_os_str().len(), p.clone()));
+        return names.iter().map(|p| Reader::open(p)?.decode()).collect();
+    }
+
+    let is_gif = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+    if is_gif {
+        let frames = GifDecoder::new(File::open(path)?)?
+            .into_frames()
+            .collect_frames()?;
+        return Ok(frames
+            .into_iter()
+            .map(|f| DynamicImage::ImageRgba8(f.into_buffer()))
+            .collect());
+    }
+
+    Ok(vec![Reader::open(path)?.decode()?])
+}
+
+// How a ball's colour is taken from the pixels underneath it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSampling {
+    // The single pixel under the ball's centre
+    Nearest,
+    // Mean of every pixel whose centre lies inside the ball
+    Area,
+    // Per-channel median of the same pixels, which keeps edges sharp
+    Median,
+}
+
+impl ImageSampling {
+    pub fn next(&self) -> Self {
+        match self {
+            ImageSampling::Nearest => ImageSampling::Area,
+            ImageSampling::Area => ImageSampling::Median,
+            ImageSampling::Median => ImageSampling::Nearest,
+        }
+    }
+}
+
+// Colour of the image under a ball, with the image stretched over `image_rect` in
+// world coordinates
+pub fn sample_image(
+    image: &RgbaImage,
+    image_rect: Rect,
+    pos: Vec2,
+    radius: f32,
+    sampling: ImageSampling,
+) -> Rgba {
+    let pixel_scale = image_rect.w() / image.width() as f32;
+    let to_color = |rgba: &[u8]| {
+        Rgba::new(
+            rgba[0] as f32 / 255.,
+            rgba[1] as f32 / 255.,
+            rgba[2] as f32 / 255.,
+            rgba[3] as f32 / 255.,
+        )
+    };
+
+    // Get relative to corner an index based on the pixel size
+    let px = (pos.x - image_rect.left()) / pixel_scale;
+    let py = (image_rect.top() - pos.y) * image.height() as f32 / image_rect.h();
+    let nearest = || {
+        let x = (px as usize).clamp(0, image.width() as usize - 1);
+        let y = (py as usize).clamp(0, image.height() as usize - 1);
+        to_color(&image.get_pixel(x as u32, y as u32).0)
+    };
+    if sampling == ImageSampling::Nearest {
+        return nearest();
+    }
+
+    let r = radius / pixel_scale;
+    let x0 = (px - r).floor().max(0.) as u32;
+    let y0 = (py - r).floor().max(0.) as u32;
+    let x1 = ((px + r).ceil() as u32).min(image.width());
+    let y1 = ((py + r).ceil() as u32).min(image.height());
+    let mut pixels = vec![];
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let centre = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            if (centre - Vec2::new(px, py)).length_squared() <= r * r {
+                pixels.push(image.get_pixel(x, y).0);
+            }
+        }
+    }
+    // Balls smaller than a pixel, or off the image, fall back to the nearest pixel
+    if pixels.is_empty() {
+        return nearest();
+    }
+
+    match sampling {
+        ImageSampling::Nearest => nearest(),
+        ImageSampling::Area => {
+            let mut sum = [0u32; 4];
+            for p in pixels.iter() {
+                for c in 0..4 {
+                    sum[c] += p[c] as u32;
+                }
+            }
+            let n = pixels.len() as f32;
+            Rgba::new(
+                sum[0] as f32 / n / 255.,
+                sum[1] as f32 / n / 255.,
+                sum[2] as f32 / n / 255.,
+                sum[3] as f32 / n / 255.,
+            )
+        }
+        ImageSampling::Median => {
+            let mut median = [0u8; 4];
+            for (c, m) in median.iter_mut().enumerate() {
+                let mut channel: Vec<u8> = pixels.iter().map(|p| p[c]).collect();
+                channel.sort_unstable();
+                *m = channel[channel.len() / 2];
+            }
+            to_color(&median)
+        }
+    }
+}
+
+fn color_distance_sqr(a: Rgba, b: Rgba) -> f32 {
+    (a.red - b.red).powi(2) + (a.green - b.green).powi(2) + (a.blue - b.blue).powi(2)
+}
+
+pub fn nearest_color(color: Rgba, palette: &[Rgba]) -> Rgba {
+    *palette
+        .iter()
+        .min_by(|a, b| color_distance_sqr(color, **a).total_cmp(&color_distance_sqr(color, **b)))
+        .unwrap()
+}
+
+// Snaps every colour to the palette. With `dither`, each ball's quantisation error is spread
+// over the neighbours that have not been visited yet, walking the pile top to bottom like
+// Floyd-Steinberg does over scanlines.
+pub fn quantize(positions: &[(Vec2, f32)], colors: &mut [Rgba], palette: &[Rgba], dither: bool) {
+    if !dither {
+        for color in colors.iter_mut() {
+            *color = nearest_color(*color, palette);
+        }
+        return;
+    }
+
+    let cell = positions.iter().map(|(_, r)| *r).fold(0., f32::max) * 3.;
+    let key = |p: Vec2| ((p.x / cell).floor() as i32, (p.y / cell).floor() as i32);
+    let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, (pos, _)) in positions.iter().enumerate() {
+        cells.entry(key(*pos)).or_default().push(i);
+    }
+
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by(|a, b| {
+        let (pa, pb) = (positions[*a].0, positions[*b].0);
+        pb.y.total_cmp(&pa.y).then(pa.x.total_cmp(&pb.x))
+    });
+
+    let mut visited = vec![false; positions.len()];
+    for i in order {
+        visited[i] = true;
+        let old = colors[i];
+        let new = nearest_color(old, palette);
+        colors[i] = Rgba::new(new.red, new.green, new.blue, old.alpha);
+        let error = [
+            old.red - new.red,
+            old.green - new.green,
+            old.blue - new.blue,
+        ];
+
+        // Neighbours within three radii, weighted towards the closest
+        let (pos, radius) = positions[i];
+        let (cx, cy) = key(pos);
+        let mut neighbours = vec![];
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(list) = cells.get(&(cx + dx, cy + dy)) {
+                    for j in list.iter().filter(|j| !visited[**j]) {
+                        let dist = (positions[*j].0 - pos).length();
+                        if dist < 3. * radius {
+                            neighbours.push((*j, 1. / dist.max(0.001)));
+                        }
+                    }
+                }
+            }
+        }
+        let total: f32 = neighbours.iter().map(|(_, w)| w).sum();
+        for (j, w) in neighbours {
+            let f = w / total;
+            let c = colors[j];
+            colors[j] = Rgba::new(
+                c.red + error[0] * f,
+                c.green + error[1] * f,
+                c.blue + error[2] * f,
+                c.alpha,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reveal_mode_round_trips() {
+        for mode in [RevealMode::Cycle { period: 4 }, RevealMode::Sequential] {
+            assert_eq!(RevealMode::parse(&mode.describe()), Ok(mode));
+        }
+        assert_eq!(
+            RevealMode::parse("cycle"),
+            Ok(RevealMode::Cycle { period: 10 })
+        );
+        for bad in ["cycle:", "cycle:0", "cycle:x", "sequential:2", "random"] {
+            assert!(RevealMode::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    fn rgba(r: f32, g: f32, b: f32) -> Rgba {
+        Rgba::new(r, g, b, 1.)
+    }
+
+    // A 4x4 image over a 4x4 world: black except for a white column at x = 3 and a
+    // red pixel at (1, 1)
+    fn test_image() -> (RgbaImage, Rect) {
+        let mut image = RgbaImage::from_pixel(4, 4, nannou::image::Rgba([0, 0, 0, 255]));
+        for y in 0..4 {
+            image.put_pixel(3, y, nannou::image::Rgba([255, 255, 255, 255]));
+        }
+        image.put_pixel(1, 1, nannou::image::Rgba([255, 0, 0, 255]));
+        (image, Rect::from_corners(Vec2::ZERO, Vec2::new(4., 4.)))
+    }
+
+    #[test]
+    fn nearest_sampling_reads_the_pixel_under_the_centre() {
+        let (image, rect) = test_image();
+        // Pixel (1, 1) counts down from the top of the rect
+        let red = sample_image(
+            &image,
+            rect,
+            Vec2::new(1.5, 2.5),
+            1.,
+            ImageSampling::Nearest,
+        );
+        assert_eq!(red, rgba(1., 0., 0.));
+        // Off the image clamps to the edge
+        let edge = sample_image(&image, rect, Vec2::new(9., 2.), 1., ImageSampling::Nearest);
+        assert_eq!(edge, rgba(1., 1., 1.));
+    }
+
+    #[test]
+    fn area_sampling_averages_and_median_keeps_the_majority() {
+        let (image, rect) = test_image();
+        // Covers the centres of pixels (1..3, 1..3): one red and three black
+        let pos = Vec2::new(2., 2.);
+        let area = sample_image(&image, rect, pos, 0.75, ImageSampling::Area);
+        assert!((area.red - 0.25).abs() < 1e-6);
+        assert_eq!((area.green, area.blue, area.alpha), (0., 0., 1.));
+        let median = sample_image(&image, rect, pos, 0.75, ImageSampling::Median);
+        assert_eq!(median, rgba(0., 0., 0.));
+        // Smaller than a pixel falls back to the nearest one
+        let tiny = sample_image(&image, rect, Vec2::new(1.5, 2.5), 0.1, ImageSampling::Area);
+        assert_eq!(tiny, rgba(1., 0., 0.));
+    }
+
+    #[test]
+    fn quantize_snaps_to_the_nearest_palette_colour() {
+        let palette = [rgba(0., 0., 0.), rgba(1., 1., 1.)];
+        assert_eq!(nearest_color(rgba(0.4, 0.4, 0.4), &palette), palette[0]);
+        assert_eq!(nearest_color(rgba(0.6, 0.6, 0.6), &palette), palette[1]);
+
+        let positions = [(Vec2::ZERO, 1.), (Vec2::new(10., 0.), 1.)];
+        let mut colors = [rgba(0.2, 0.2, 0.2), rgba(0.9, 0.9, 0.9)];
+        quantize(&positions, &mut colors, &palette, false);
+        assert_eq!(colors, palette);
+    }
+
+    #[test]
+    fn dithering_spreads_the_error_to_unvisited_neighbours() {
+        let palette = [rgba(0., 0., 0.), rgba(1., 1., 1.)];
+        // Two touching mid-grey balls: the top one rounds up to white, pushing the one
+        // below towards black
+        let positions = [(Vec2::new(0., 2.), 1.), (Vec2::ZERO, 1.)];
+        let mut colors = [rgba(0.6, 0.6, 0.6), rgba(0.6, 0.6, 0.6)];
+        quantize(&positions, &mut colors, &palette, true);
+        assert_eq!(colors, [palette[1], palette[0]]);
+    }
+
+    #[test]
+    fn dithering_survives_nan_positions() {
+        let palette = [rgba(0., 0., 0.), rgba(1., 1., 1.)];
+        let positions = [(Vec2::new(f32::NAN, 0.), 1.), (Vec2::ZERO, 1.)];
+        let mut colors = [rgba(0.2, 0.2, 0.2), rgba(0.8, 0.8, 0.8)];
+        quantize(&positions, &mut colors, &palette, true);
+        for color in colors {
+            assert!(palette.contains(&color));
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use crate::ball::*;
+use crate::boundary::*;
+use crate::cluster::*;
+use crate::link::*;
+use crate::motion::*;
+use crate::partition::*;
+use crate::sdf::*;
+use crate::sensor::*;
+use crate::solver::*;
+use nannou::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Scene files hold `Solver::describe` followed by any settings the viewer keeps
+// outside the solver, one setting or shape per line
+
+const SHAPES: [&str; 7] = [
+    "rect", "circle", "capsule", "ellipse", "annulus", "polygon", "mask",
+];
+
+pub fn save_scene<P: AsRef<Path>>(path: P, description: &str) -> io::Result<()> {
+    fs::write(path, description.to_string() + "\n")
+}
+
+fn parse_vec2(value: &str) -> Result<Vec2, String> {
+    let mut parts = value.split(',').map(|v| v.trim().parse::<f32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok(Vec2::new(x, y)),
+        _ => Err(format!("expected x,y but found {}", value)),
+    }
+}
+
+// Comma separated numbers, empty if any of them isn't one
+fn parse_floats(value: &str) -> Vec<f32> {
+    value
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .unwrap_or_default()
+}
+
+// The `key=value` pairs after a shape or spawner's name
+pub struct Fields<'a> {
+    pub name: &'a str,
+    values: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Fields<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty line")?;
+        let values = words
+            .map(|word| {
+                word.split_once('=')
+                    .ok_or(format!("expected key=value but found {}", word))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Fields { name, values })
+    }
+
+    pub fn get(&self, key: &str) -> Result<&'a str, String> {
+        self.values
+            .get(key)
+            .copied()
+            .ok_or(format!("{} is missing {}", self.name, key))
+    }
+
+    pub fn f32(&self, key: &str) -> Result<f32, String> {
+        let value = self.get(key)?;
+        value
+            .parse()
+            .map_err(|_| format!("{}={} is not a number", key, value))
+    }
+
+    pub fn usize(&self, key: &str) -> Result<usize, String> {
+        let value = self.get(key)?;
+        value
+            .parse()
+            .map_err(|_| format!("{}={} is not a whole number", key, value))
+    }
+
+    fn vec2(&self, key: &str) -> Result<Vec2, String> {
+        parse_vec2(self.get(key)?)
+    }
+
+    fn kind(&self) -> Result<BoundaryType, String> {
+        match self.get("kind")? {
+            "inner" => Ok(BoundaryType::Inner),
+            "outer" => Ok(BoundaryType::Outer),
+            kind => Err(format!("unknown kind {}", kind)),
+        }
+    }
+
+    fn sink(&self) -> Result<bool, String> {
+        let value = self.get("sink")?;
+        value
+            .parse()
+            .map_err(|_| format!("sink={} is not true or false", value))
+    }
+
+    pub fn filter(&self) -> Result<CollisionFilter, String> {
+        let hex = |key: &str| {
+            let value = self.get(key)?;
+            u32::from_str_radix(value, 16).map_err(|_| format!("{}={} is not hex", key, value))
+        };
+        Ok(CollisionFilter::new(hex("layers")?, hex("mask")?))
+    }
+}
+
+// Rebuilds a shape from its `Boundary::describe` line
+pub fn parse_boundary(line: &str) -> Result<Box<dyn Boundary>, String> {
+    let f = Fields::parse(line)?;
+    let (pos, kind, sink, filter) = (f.vec2("pos")?, f.kind()?, f.sink()?, f.filter()?);
+    let bound: Box<dyn Boundary> = match f.name {
+        "rect" => Box::new(RectBound {
+            pos,
+            kind,
+            width: f.f32("width")?,
+            height: f.f32("height")?,
+            sink,
+            filter,
+        }),
+        "circle" => Box::new(CircleBound {
+            pos,
+            radius: f.f32("radius")?,
+            kind,
+            sink,
+            filter,
+        }),
+        "capsule" => Box::new(CapsuleBound {
+            pos,
+            length: f.f32("length")?,
+            angle: f.f32("angle")?,
+            radius: f.f32("radius")?,
+            kind,
+            sink,
+            filter,
+        }),
+        "ellipse" => Box::new(EllipseBound {
+            pos,
+            half_width: f.f32("half_width")?,
+            half_height: f.f32("half_height")?,
+            angle: f.f32("angle")?,
+            kind,
+            sink,
+            filter,
+        }),
+        "annulus" => Box::new(AnnulusBound {
+            pos,
+            inner_radius: f.f32("inner_radius")?,
+            outer_radius: f.f32("outer_radius")?,
+            kind,
+            sink,
+            filter,
+        }),
+        "polygon" => Box::new(PolygonBound {
+            pos,
+            points: f
+                .get("points")?
+                .split(';')
+                .map(parse_vec2)
+                .collect::<Result<_, _>>()?,
+            kind,
+            sink,
+            filter,
+        }),
+        // The image is read again and must still give the same distance field
+        "mask" => {
+            let threshold = f.get("threshold")?;
+            let threshold = threshold
+                .parse()
+                .map_err(|_| format!("threshold={} is not a byte", threshold))?;
+            let mut mask = MaskBound::open(f.get("image")?, threshold, kind)?;
+            mask.pos = pos;
+            mask.pixel_scale = f.f32("pixel_scale")?;
+            mask.sink = sink;
+            mask.filter = filter;
+            if format!("{:x}", mask.checksum()) != f.get("checksum")? {
+                return Err(format!(
+                    "{} has changed since the scene was saved",
+                    mask.source
+                ));
+            }
+            Box::new(mask)
+        }
+        name => return Err(format!("unknown shape {}", name)),
+    };
+    Ok(bound)
+}
+
+// Rebuilds a ball from its `Ball::describe` line, at rest where it was saved
+pub fn parse_ball(line: &str) -> Result<Ball, String> {
+    let f = Fields::parse(line)?;
+    let pos = f.vec2("pos")?;
+    let color = f.get("color")?;
+    let color = match parse_floats(color)[..] {
+        [h, s, v] => Hsv::new(h, s, v),
+        _ => return Err(format!("expected h,s,v but found {}", color)),
+    };
+    let mut ball = Ball::new(pos, pos, f.f32("radius")?, color);
+    ball.filter = f.filter()?;
+    ball.spawn_index = PAINTED;
+    if let Ok(motion) = f.get("motion") {
+        ball.motion = parse_motion(motion)?;
+    }
+    Ok(ball)
+}
+
+fn parse_motion(value: &str) -> Result<Motion, String> {
+    let numbers = |prefix: &str| value.strip_prefix(prefix).map(parse_floats);
+    if value == "dynamic" {
+        Ok(Motion::Dynamic)
+    } else if value == "fixed" {
+        Ok(Motion::Fixed)
+    } else if let Some([x, y, radius, speed, phase]) = numbers("circle:").as_deref() {
+        Ok(Motion::Kinematic(crate::motion::Path::Circle {
+            center: Vec2::new(*x, *y),
+            radius: *radius,
+            speed: *speed,
+            phase: *phase,
+        }))
+    } else if let Some([x0, y0, x1, y1, period]) = numbers("line:").as_deref() {
+        Ok(Motion::Kinematic(crate::motion::Path::Line {
+            from: Vec2::new(*x0, *y0),
+            to: Vec2::new(*x1, *y1),
+            period: *period,
+        }))
+    } else {
+        Err(format!("unknown motion {}", value))
+    }
+}
+
+fn parse_indices(value: &str) -> Result<Vec<usize>, String> {
+    value
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("expected whole numbers but found {}", value))
+}
+
+// Rebuilds an area constraint from its `AreaConstraint::describe` line. Ball indices are
+// left as places in the list of saved balls.
+pub fn parse_area(line: &str) -> Result<AreaConstraint, String> {
+    let f = Fields::parse(line)?;
+    Ok(AreaConstraint {
+        balls: parse_indices(f.get("balls")?)?,
+        area: f.f32("area")?,
+        stiffness: f.f32("stiffness")?,
+    })
+}
+
+// Rebuilds a cluster from its `RigidCluster::describe` line. Ball indices are left as
+// places in the list of saved balls.
+pub fn parse_cluster(line: &str) -> Result<RigidCluster, String> {
+    let f = Fields::parse(line)?;
+    let cluster = RigidCluster {
+        balls: parse_indices(f.get("balls")?)?,
+        offsets: f
+            .get("offsets")?
+            .split(';')
+            .map(parse_vec2)
+            .collect::<Result<_, _>>()?,
+        weights: parse_floats(f.get("weights")?),
+    };
+    if cluster.offsets.len() != cluster.balls.len() || cluster.weights.len() != cluster.balls.len()
+    {
+        return Err("cluster needs one offset and weight per ball".to_string());
+    }
+    Ok(cluster)
+}
+
+// Rebuilds a link from its `Link::describe` line. Ball indices are left as places in the
+// list of saved balls.
+pub fn parse_link(line: &str) -> Result<Link, String> {
+    let f = Fields::parse(line)?;
+    let b = if let Ok(b) = f.usize("ball") {
+        Anchor::Ball(b)
+    } else if let Ok(point) = f.vec2("point") {
+        Anchor::Point(point)
+    } else if let Ok(index) = f.usize("boundary") {
+        Anchor::Boundary {
+            index,
+            offset: f.vec2("offset")?,
+        }
+    } else {
+        return Err("link needs a ball, point or boundary".to_string());
+    };
+    let kind = match f.get("kind")? {
+        "rigid" => LinkKind::Rigid,
+        "rope" => LinkKind::Rope,
+        kind => match kind.strip_prefix("spring:").map(parse_floats).as_deref() {
+            Some([stiffness, damping]) => LinkKind::Spring {
+                stiffness: *stiffness,
+                damping: *damping,
+            },
+            _ => return Err(format!("unknown link kind {}", kind)),
+        },
+    };
+    let mut link = Link::new(f.usize("a")?, b, f.f32("length")?, kind);
+    if f.get("max_strain").is_ok() {
+        link.max_strain = Some(f.f32("max_strain")?);
+    }
+    Ok(link)
+}
+
+fn parse_action(action: &str) -> Result<SensorAction, String> {
+    match action.split_once(':') {
+        None if action == "remove" => Ok(SensorAction::Remove),
+        None if action == "count" => Ok(SensorAction::Count),
+        Some(("impulse", value)) => Ok(SensorAction::Impulse(parse_vec2(value)?)),
+        Some(("recolor", value)) => match parse_floats(value)[..] {
+            [h, s, v] => Ok(SensorAction::Recolor(Hsv::new(h, s, v))),
+            _ => Err(format!("expected h,s,v but found {}", value)),
+        },
+        _ => Err(format!("unknown sensor action {}", action)),
+    }
+}
+
+// Rebuilds a sensor from its `Sensor::describe` line
+pub fn parse_sensor(line: &str) -> Result<Sensor, String> {
+    let shape = line.strip_prefix("sensor ").ok_or("not a sensor line")?;
+    let actions = match Fields::parse(shape)?.get("actions")? {
+        "none" => vec![],
+        actions => actions
+            .split(';')
+            .map(parse_action)
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(Sensor::new(parse_boundary(shape)?, actions))
+}
+
+// Replaces the solver's settings, boundaries, sensors and constraints with those in the
+// file, adding the saved balls. A shape line starting with `mouse` becomes the shape that
+// follows the mouse. Lines meant for something other than the solver are returned for the
+// caller to apply.
+pub fn load_scene<P: AsRef<Path>>(path: P, solver: &mut Solver) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    // Everything is parsed before anything is applied, so a bad file leaves the solver
+    // as it was
+    let mut gravity = solver.gravity;
+    let mut substeps = solver.substeps;
+    let mut detect_mode = solver.detect_mode;
+    let mut periodic = solver.hash.periodic;
+    let mut boundaries = vec![];
+    let mut mouse_boundary = None;
+    let mut sensors = vec![];
+    let mut balls = vec![];
+    let mut links = vec![];
+    let mut areas = vec![];
+    let mut clusters = vec![];
+    let mut rest = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with("sensor ") {
+            if sensors.len() == MAX_SENSORS {
+                return Err(error(format!(
+                    "at most {} sensors are supported",
+                    MAX_SENSORS
+                )));
+            }
+            sensors.push(parse_sensor(line).map_err(error)?);
+            continue;
+        }
+        match line.split_once('=') {
+            Some(("gravity", value)) => gravity = parse_vec2(value).map_err(error)?,
+            Some(("substeps", value)) => {
+                substeps = match value.parse() {
+                    Ok(n) if n >= 1 => n,
+                    _ => {
+                        return Err(error(format!(
+                            "substeps={} is not a positive whole number",
+                            value
+                        )))
+                    }
+                }
+            }
+            Some(("detect_mode", value)) => {
+                detect_mode = match value {
+                    "SpatialPartition" => DetectMode::SpatialPartition,
+                    "Slow" => DetectMode::Slow,
+                    _ => return Err(error(format!("unknown detect_mode {}", value))),
+                }
+            }
+            Some(("periodic", value)) => periodic = Periodic::parse(value).map_err(error)?,
+            _ if line.starts_with("ball ") => balls.push(parse_ball(line).map_err(error)?),
+            _ if line.starts_with("link ") => links.push(parse_link(line).map_err(error)?),
+            _ if line.starts_with("area ") => areas.push(parse_area(line).map_err(error)?),
+            _ if line.starts_with("cluster ") => clusters.push(parse_cluster(line).map_err(error)?),
+            _ if line.starts_with("mouse ") => {
+                mouse_boundary = Some(boundaries.len());
+                boundaries.push(parse_boundary(&line["mouse ".len()..]).map_err(error)?)
+            }
+            _ if SHAPES
+                .iter()
+                .any(|shape| line.split_whitespace().next() == Some(*shape)) =>
+            {
+                boundaries.push(parse_boundary(line).map_err(error)?)
+            }
+            _ => rest.push(line.to_string()),
+        }
+    }
+    for link in links.iter() {
+        let ball_ok = |i: usize| i < balls.len();
+        let anchor_ok = match link.b {
+            Anchor::Ball(b) => ball_ok(b),
+            Anchor::Point(_) => true,
+            Anchor::Boundary { index, .. } => index < boundaries.len(),
+        };
+        if !ball_ok(link.a) || !anchor_ok {
+            return Err(format!(
+                "{} refers to a missing ball or shape",
+                link.describe(link.a, link.b)
+            ));
+        }
+    }
+    let members = areas
+        .iter()
+        .flat_map(|area| area.balls.iter())
+        .chain(clusters.iter().flat_map(|c| c.balls.iter()));
+    if let Some(i) = members.copied().find(|i| *i >= balls.len()) {
+        return Err(format!("ball {} of an area or cluster is missing", i));
+    }
+    let members = clusters.iter().flat_map(|c| c.balls.iter());
+    if let Some(i) = members
+        .copied()
+        .find(|i| balls[*i].motion != Motion::Dynamic)
+    {
+        return Err(format!("ball {} of a cluster isn't dynamic", i));
+    }
+    solver.gravity = gravity;
+    solver.substeps = substeps;
+    solver.detect_mode = detect_mode;
+    solver.hash = solver.hash.with_periodic(periodic);
+    solver.boundaries = boundaries;
+    solver.mouse_boundary = mouse_boundary;
+    solver.sensors = sensors;
+    // Saved balls go after any already in the solver, and come back every run
+    let first = solver.balls.len();
+    for ball in balls {
+        solver.add_fixture(ball);
+    }
+    solver.links.clear();
+    solver.setup.links.clear();
+    for mut link in links {
+        link.a += first;
+        if let Anchor::Ball(b) = &mut link.b {
+            *b += first;
+        }
+        solver.add_link(link);
+    }
+    solver.areas.clear();
+    solver.setup.areas.clear();
+    for mut area in areas {
+        area.balls.iter_mut().for_each(|i| *i += first);
+        solver.add_area(area);
+    }
+    solver.clusters.clear();
+    solver.setup.clusters.clear();
+    for mut cluster in clusters {
+        cluster.balls.iter_mut().for_each(|i| *i += first);
+        solver.add_rigid(cluster);
+    }
+    Ok(rest)
+}
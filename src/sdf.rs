@@ -0,0 +1,483 @@
+use crate::ball::*;
+use crate::boundary::*;
+use crate::colormap::*;
+use nannou::image::io::Reader;
+use nannou::image::{DynamicImage, GenericImageView};
+use nannou::prelude::*;
+use std::collections::HashMap;
+
+// Signed distance field sampled on the pixel grid of a mask, negative inside the solid
+pub struct DistanceField {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f32>,
+}
+
+impl DistanceField {
+    pub fn from_mask(mask: &[bool], width: usize, height: usize) -> Self {
+        let outside = squared_distance_transform(mask, width, height, true);
+        let inside = squared_distance_transform(mask, width, height, false);
+        let values = outside
+            .iter()
+            .zip(inside.iter())
+            .map(|(o, i)| {
+                // Half a pixel puts the zero crossing on the pixel edge, where the contours are
+                if *o > 0. {
+                    o.sqrt() - 0.5
+                } else {
+                    0.5 - i.sqrt()
+                }
+            })
+            .collect();
+        DistanceField {
+            width,
+            height,
+            values,
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.values[y * self.width + x]
+    }
+
+    // Bilinear sample in pixel coordinates, extrapolating linearly past the edges
+    pub fn sample(&self, p: Vec2) -> f32 {
+        let max = Vec2::new((self.width - 1) as f32, (self.height - 1) as f32);
+        let clamped = p.clamp(Vec2::ZERO, max);
+        let x0 = clamped.x.floor() as usize;
+        let y0 = clamped.y.floor() as usize;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let fx = clamped.x - x0 as f32;
+        let fy = clamped.y - y0 as f32;
+        let top = self.at(x0, y0) * (1. - fx) + self.at(x1, y0) * fx;
+        let bot = self.at(x0, y1) * (1. - fx) + self.at(x1, y1) * fx;
+        top * (1. - fy) + bot * fy + (p - clamped).length()
+    }
+
+    // Direction of increasing distance in pixel coordinates
+    pub fn gradient(&self, p: Vec2) -> Vec2 {
+        let h = 0.5;
+        let dx = self.sample(p + Vec2::new(h, 0.)) - self.sample(p - Vec2::new(h, 0.));
+        let dy = self.sample(p + Vec2::new(0., h)) - self.sample(p - Vec2::new(0., h));
+        Vec2::new(dx, dy).normalize_or_zero()
+    }
+}
+
+// Exact squared euclidean distance to the nearest pixel whose mask value equals `target`,
+// done as separable 1D passes (Felzenszwalb & Huttenlocher)
+fn squared_distance_transform(
+    mask: &[bool],
+    width: usize,
+    height: usize,
+    target: bool,
+) -> Vec<f32> {
+    let far = ((width * width + height * height) as f32) * 4.;
+    let mut grid: Vec<f32> = mask
+        .iter()
+        .map(|m| if *m == target { 0. } else { far })
+        .collect();
+
+    let mut line = vec![0.; width.max(height)];
+    let mut out = vec![0.; width.max(height)];
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&line[..height], &mut out[..height]);
+        for y in 0..height {
+            grid[y * width + x] = out[y];
+        }
+    }
+    for y in 0..height {
+        line[..width].copy_from_slice(&grid[y * width..(y + 1) * width]);
+        distance_transform_1d(&line[..width], &mut out[..width]);
+        grid[y * width..(y + 1) * width].copy_from_slice(&out[..width]);
+    }
+    grid
+}
+
+fn distance_transform_1d(f: &[f32], d: &mut [f32]) {
+    let n = f.len();
+    let mut v = vec![0usize; n];
+    let mut z = vec![0f32; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    for q in 1..n {
+        loop {
+            let p = v[k];
+            let s = ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2. * (q - p) as f32);
+            if s <= z[k] && k > 0 {
+                k -= 1;
+            } else {
+                k += 1;
+                v[k] = q;
+                z[k] = s;
+                z[k + 1] = f32::INFINITY;
+                break;
+            }
+        }
+    }
+    k = 0;
+    for (q, dq) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let p = v[k];
+        *dq = (q as f32 - p as f32).powi(2) + f[p];
+    }
+}
+
+// Marching squares over the mask, returning polylines in pixel coordinates with pixel centres
+// on integers. Pixels outside the image count as empty so every contour closes.
+pub fn extract_contours(mask: &[bool], width: usize, height: usize) -> Vec<Vec<Vec2>> {
+    let filled = |x: isize, y: isize| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && mask[y as usize * width + x as usize]
+    };
+
+    // Edge midpoints are keyed in doubled integer coordinates so shared points match exactly
+    let mut segments: Vec<((i32, i32), (i32, i32))> = vec![];
+    for y in -1..height as isize {
+        for x in -1..width as isize {
+            let tl = filled(x, y);
+            let tr = filled(x + 1, y);
+            let br = filled(x + 1, y + 1);
+            let bl = filled(x, y + 1);
+            let (xi, yi) = (2 * x as i32, 2 * y as i32);
+            let top = (xi + 1, yi);
+            let right = (xi + 2, yi + 1);
+            let bot = (xi + 1, yi + 2);
+            let left = (xi, yi + 1);
+            let case = (tl as u8) << 3 | (tr as u8) << 2 | (br as u8) << 1 | bl as u8;
+            match case {
+                1 | 14 => segments.push((left, bot)),
+                2 | 13 => segments.push((bot, right)),
+                3 | 12 => segments.push((left, right)),
+                4 | 11 => segments.push((top, right)),
+                6 | 9 => segments.push((top, bot)),
+                7 | 8 => segments.push((left, top)),
+                5 => {
+                    segments.push((left, top));
+                    segments.push((bot, right));
+                }
+                10 => {
+                    segments.push((top, right));
+                    segments.push((left, bot));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Join segments sharing endpoints into polylines
+    let mut adjacency: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        adjacency.entry(*a).or_default().push(i);
+        adjacency.entry(*b).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    let mut contours = vec![];
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (first, mut current) = segments[start];
+        let mut points = vec![first, current];
+        while let Some(next) = adjacency[&current].iter().find(|i| !used[**i]) {
+            used[*next] = true;
+            let (a, b) = segments[*next];
+            current = if a == current { b } else { a };
+            points.push(current);
+        }
+        contours.push(
+            points
+                .iter()
+                .map(|(x, y)| Vec2::new(*x as f32 / 2., *y as f32 / 2.))
+                .collect(),
+        );
+    }
+    contours
+}
+
+// Ramer-Douglas-Peucker, keeping points further than `tolerance` from the simplified line
+pub fn simplify_polyline(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let chord = last - first;
+    let (index, dist) = points[1..points.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let dist = if chord.length_squared() > 0. {
+                (chord.perp_dot(*p - first) / chord.length()).abs()
+            } else {
+                (*p - first).length()
+            };
+            (i + 1, dist)
+        })
+        .fold(
+            (0, 0.),
+            |best, next| if next.1 > best.1 { next } else { best },
+        );
+
+    if dist > tolerance {
+        let mut left = simplify_polyline(&points[..=index], tolerance);
+        let right = simplify_polyline(&points[index..], tolerance);
+        left.pop();
+        left.extend(right);
+        left
+    } else {
+        vec![first, last]
+    }
+}
+
+// Collision geometry drawn as an image. Dark pixels are solid: with `BoundaryType::Outer`
+// balls are kept out of them, with `BoundaryType::Inner` balls are kept inside them.
+pub struct MaskBound {
+    pub pos: Vec2,
+    pub kind: BoundaryType,
+    pub sink: bool,
+    pub pixel_scale: f32,
+    pub filter: CollisionFilter,
+    pub field: DistanceField,
+    pub contours: Vec<Vec<Vec2>>,
+    // Image file the mask was read from, so scene files can rebuild it
+    pub source: String,
+    pub threshold: u8,
+}
+
+impl MaskBound {
+    // Reads the mask from an image file, one world unit per pixel until it is fitted
+    pub fn open(path: &str, threshold: u8, kind: BoundaryType) -> Result<Self, String> {
+        let image = Reader::open(path)
+            .map_err(|e| e.to_string())?
+            .decode()
+            .map_err(|e| e.to_string())?;
+        let mut mask = MaskBound::from_image(&image, 1., threshold, kind);
+        mask.source = path.to_string();
+        Ok(mask)
+    }
+
+    // Stretches the mask across the width of `rect`, centred on it, the same way
+    // `Solver::image_rect` places the colour image
+    pub fn fit_width(&mut self, rect: Rect) {
+        self.pos = rect.xy();
+        self.pixel_scale = rect.w() / self.field.width as f32;
+    }
+
+    // Pixels darker than `threshold` are solid. The image is centred on the origin.
+    pub fn from_image(
+        image: &DynamicImage,
+        pixel_scale: f32,
+        threshold: u8,
+        kind: BoundaryType,
+    ) -> Self {
+        let luma = image.to_luma8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mask: Vec<bool> = luma.pixels().map(|p| p[0] < threshold).collect();
+        let contours = extract_contours(&mask, width, height)
+            .iter()
+            .map(|c| simplify_polyline(c, 0.5))
+            .collect();
+        MaskBound {
+            pos: Vec2::ZERO,
+            kind,
+            sink: false,
+            filter: CollisionFilter::default(),
+            pixel_scale,
+            field: DistanceField::from_mask(&mask, width, height),
+            contours,
+            source: String::new(),
+            threshold,
+        }
+    }
+
+    pub fn checksum(&self) -> u64 {
+        let bytes: Vec<u8> = self
+            .field
+            .values
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        fnv1a(&bytes)
+    }
+
+    // Pixel coordinates have pixel centres on integers, matching the distance field samples
+    fn to_pixel(&self, p: Vec2) -> Vec2 {
+        let local = (p - self.pos) / self.pixel_scale;
+        Vec2::new(
+            local.x + self.field.width as f32 / 2. - 0.5,
+            self.field.height as f32 / 2. - local.y - 0.5,
+        )
+    }
+
+    fn to_world(&self, p: Vec2) -> Vec2 {
+        let local = Vec2::new(
+            p.x + 0.5 - self.field.width as f32 / 2.,
+            self.field.height as f32 / 2. - p.y - 0.5,
+        );
+        self.pos + local * self.pixel_scale
+    }
+
+    // Signed distance in world units and the outward normal from the solid
+    fn distance(&self, p: Vec2) -> (f32, Vec2) {
+        let pixel = self.to_pixel(p);
+        let gradient = self.field.gradient(pixel);
+        (
+            self.field.sample(pixel) * self.pixel_scale,
+            Vec2::new(gradient.x, -gradient.y),
+        )
+    }
+}
+
+impl Boundary for MaskBound {
+    fn sink(&self) -> bool {
+        self.sink
+    }
+    fn filter(&self) -> CollisionFilter {
+        self.filter
+    }
+    fn kind(&self) -> BoundaryType {
+        self.kind
+    }
+    fn set_pos(&mut self, new_pos: Vec2) {
+        self.pos = new_pos;
+    }
+    fn pos(&self) -> Vec2 {
+        self.pos
+    }
+    fn set_kind(&mut self, kind: BoundaryType) {
+        self.kind = kind;
+    }
+    fn set_sink(&mut self, sink: bool) {
+        self.sink = sink;
+    }
+    // The mask comes from an image and can only be moved
+    fn handles(&self) -> Vec<Vec2> {
+        vec![]
+    }
+    fn set_handle(&mut self, _index: usize, _pos: Vec2) {}
+    fn apply_inner_constraint(&self, ball: &mut Ball) {
+        let (dist, normal) = self.distance(ball.pos);
+        ball.pos -= normal * (dist + ball.radius);
+    }
+    fn apply_outer_constraint(&self, ball: &mut Ball) {
+        let (dist, normal) = self.distance(ball.pos);
+        ball.pos += normal * (ball.radius - dist);
+    }
+    fn detect_inner_collision(&self, ball: &Ball) -> bool {
+        self.field.sample(self.to_pixel(ball.pos)) * self.pixel_scale > -ball.radius
+    }
+    fn detect_outer_collision(&self, ball: &Ball) -> bool {
+        self.field.sample(self.to_pixel(ball.pos)) * self.pixel_scale < ball.radius
+    }
+
+    fn outline(&self) -> Vec<Vec<Vec2>> {
+        self.contours
+            .iter()
+            .map(|contour| contour.iter().map(|p| self.to_world(*p)).collect())
+            .collect()
+    }
+
+    fn describe(&self) -> String {
+        // The mask itself is summarised by a checksum of its distance field
+        format!(
+            "mask pos={},{} image={} threshold={} pixel_scale={} width={} height={} checksum={:x} {}",
+            self.pos.x,
+            self.pos.y,
+            self.source,
+            self.threshold,
+            self.pixel_scale,
+            self.field.width,
+            self.field.height,
+            self.checksum(),
+            describe_common(self.kind, self.sink, self.filter)
+        )
+    }
+
+    fn draw(&self, draw: &Draw) {
+        for points in self.outline() {
+            draw.polyline()
+                .stroke_weight(1.)
+                .color(WHITE)
+                .points(points);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `size` by `size` mask with a solid square of side `side` in the middle
+    fn square_mask(size: usize, side: usize) -> Vec<bool> {
+        let lo = (size - side) / 2;
+        let hi = lo + side;
+        (0..size * size)
+            .map(|i| (lo..hi).contains(&(i % size)) && (lo..hi).contains(&(i / size)))
+            .collect()
+    }
+
+    #[test]
+    fn distance_field_is_signed_from_the_pixel_edges() {
+        let field = DistanceField::from_mask(&square_mask(7, 3), 7, 7);
+        let at = |x: usize, y: usize| field.values[y * 7 + x];
+        assert_eq!(at(3, 3), -1.5);
+        assert_eq!(at(2, 3), -0.5);
+        assert_eq!(at(1, 3), 0.5);
+        assert_eq!(at(0, 3), 1.5);
+        assert!((at(1, 1) - (2f32.sqrt() - 0.5)).abs() < 1e-6);
+        assert!((at(0, 0) - (8f32.sqrt() - 0.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distance_field_samples_between_pixels_and_past_the_edge() {
+        let field = DistanceField::from_mask(&square_mask(7, 3), 7, 7);
+        assert!((field.sample(Vec2::new(1.5, 3.)) - 0.).abs() < 1e-6);
+        assert!((field.sample(Vec2::new(-2., 3.)) - 3.5).abs() < 1e-6);
+        assert!(field
+            .gradient(Vec2::new(1., 3.))
+            .abs_diff_eq(Vec2::new(-1., 0.), 1e-6));
+    }
+
+    #[test]
+    fn contours_close_around_each_solid_region() {
+        let contours = extract_contours(&square_mask(5, 1), 5, 5);
+        assert_eq!(contours.len(), 1);
+        let contour = &contours[0];
+        assert_eq!(contour.first(), contour.last());
+        assert_eq!(contour.len(), 5);
+        for point in contour.iter() {
+            assert_eq!(point.distance(Vec2::new(2., 2.)), 0.5);
+        }
+
+        let mut mask = vec![false; 5 * 5];
+        mask[0] = true;
+        mask[4 * 5 + 4] = true;
+        assert_eq!(extract_contours(&mask, 5, 5).len(), 2);
+        assert!(extract_contours(&[false; 9], 3, 3).is_empty());
+    }
+
+    #[test]
+    fn contours_follow_the_region_outline() {
+        let contours = extract_contours(&square_mask(7, 3), 7, 7);
+        assert_eq!(contours.len(), 1);
+        let simplified = simplify_polyline(&contours[0], 0.1);
+        // An octagon on the pixel edges, where the distance field crosses zero, plus the
+        // repeated start
+        assert_eq!(simplified.len(), 9);
+        for point in simplified.iter() {
+            let offset = (*point - Vec2::splat(3.)).abs();
+            assert_eq!(offset.max_element(), 1.5);
+        }
+    }
+}
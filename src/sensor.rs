@@ -0,0 +1,142 @@
+use crate::ball::*;
+use crate::boundary::*;
+use nannou::prelude::*;
+
+// Most sensors a solver can hold, one bit each in `Ball::sensors`
+pub const MAX_SENSORS: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub enum SensorAction {
+    Recolor(Hsv),
+    Remove,
+    Impulse(Vec2),
+    Count,
+}
+
+impl SensorAction {
+    pub fn describe(&self) -> String {
+        match self {
+            SensorAction::Recolor(color) => format!(
+                "recolor:{},{},{}",
+                color.hue.to_positive_degrees(),
+                color.saturation,
+                color.value
+            ),
+            SensorAction::Remove => "remove".to_string(),
+            SensorAction::Impulse(dv) => format!("impulse:{},{}", dv.x, dv.y),
+            SensorAction::Count => "count".to_string(),
+        }
+    }
+}
+
+// Action sets the editor can give a shape when it turns it into a sensor
+pub fn sensor_presets() -> Vec<(&'static str, Vec<SensorAction>)> {
+    vec![
+        ("count", vec![SensorAction::Count]),
+        ("drain", vec![SensorAction::Count, SensorAction::Remove]),
+        ("paint", vec![SensorAction::Recolor(Hsv::new(0., 1., 1.))]),
+        ("kick", vec![SensorAction::Impulse(Vec2::new(0., 2.))]),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorEventKind {
+    Enter,
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SensorEvent {
+    pub sensor: usize,
+    // Index into `Solver::balls`, or `None` once the ball has been removed
+    pub ball: Option<usize>,
+    pub kind: SensorEventKind,
+}
+
+// A boundary shape that never constrains balls and only reports them. A ball is inside the
+// sensor whenever the shape would have pushed it, so an `Outer` shape watches its interior and
+// an `Inner` shape watches everything beyond its walls.
+pub struct Sensor {
+    pub shape: Box<dyn Boundary>,
+    pub actions: Vec<SensorAction>,
+    // Entries seen while the sensor has a `Count` action
+    pub count: usize,
+}
+
+impl Sensor {
+    pub fn new(shape: Box<dyn Boundary>, actions: Vec<SensorAction>) -> Self {
+        Sensor {
+            shape,
+            actions,
+            count: 0,
+        }
+    }
+
+    pub fn contains(&self, ball: &Ball) -> bool {
+        self.shape.filter().interacts(&ball.filter)
+            && match self.shape.kind() {
+                BoundaryType::Inner => self.shape.detect_inner_collision(ball),
+                BoundaryType::Outer => self.shape.detect_outer_collision(ball),
+            }
+    }
+
+    // Applies the entry actions to a ball, returning whether it should be removed
+    pub fn trigger(&mut self, ball: &mut Ball) -> bool {
+        let mut remove = false;
+        for action in self.actions.iter() {
+            match action {
+                SensorAction::Recolor(color) => ball.color = *color,
+                SensorAction::Remove => remove = true,
+                SensorAction::Impulse(dv) => ball.impulse(*dv),
+                SensorAction::Count => self.count += 1,
+            }
+        }
+        remove
+    }
+
+    // The shape's line with the actions added, see `parse_sensor`
+    pub fn describe(&self) -> String {
+        let actions: Vec<String> = self.actions.iter().map(|a| a.describe()).collect();
+        let actions = if actions.is_empty() {
+            "none".to_string()
+        } else {
+            actions.join(";")
+        };
+        format!("sensor {} actions={}", self.shape.describe(), actions)
+    }
+
+    pub fn draw(&self, draw: &Draw) {
+        self.shape.draw(draw);
+    }
+}
+
+// Entries and exits of each sensor in `events`, plus the running count of those that count
+pub fn describe_sensors(sensors: &[Sensor], events: &[SensorEvent]) -> String {
+    sensors
+        .iter()
+        .enumerate()
+        .map(|(s, sensor)| {
+            let seen = |kind| {
+                events
+                    .iter()
+                    .filter(|e| e.sensor == s && e.kind == kind)
+                    .count()
+            };
+            let mut line = format!(
+                "sensor {} +{} -{}",
+                s,
+                seen(SensorEventKind::Enter),
+                seen(SensorEventKind::Exit)
+            );
+            if sensor
+                .actions
+                .iter()
+                .any(|a| matches!(a, SensorAction::Count))
+            {
+                line += &format!(" count {}", sensor.count);
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -1,6 +1,15 @@
 use crate::ball::*;
 use crate::boundary::*;
+use crate::cluster::*;
+use crate::contact::*;
+use crate::lattice::*;
+use crate::link::*;
+use crate::motion::*;
+use crate::palette::*;
 use crate::partition::*;
+use crate::reveal::*;
+use crate::sensor::*;
+use crate::stats::*;
 use nannou::color::Rgba;
 use nannou::image::io::Reader;
 use nannou::image::DynamicImage;
@@ -10,79 +19,787 @@ use std::cell::RefCell;
 use std::io::Cursor;
 use std::time::Instant;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DetectMode {
     SpatialPartition,
     Slow,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColorMode {
+    // Colours from `Solver::colormap`, which is what spawners and image reveals write.
+    // Balls keep whatever colour they are given, so sensors can still recolour them.
+    Colormap,
     Velocity,
     Collide,
     Index,
+    Pressure,
+    Height,
+}
+
+impl ColorMode {
+    pub fn next(&self) -> ColorMode {
+        match self {
+            ColorMode::Colormap => ColorMode::Velocity,
+            ColorMode::Velocity => ColorMode::Collide,
+            ColorMode::Collide => ColorMode::Index,
+            ColorMode::Index => ColorMode::Pressure,
+            ColorMode::Pressure => ColorMode::Height,
+            ColorMode::Height => ColorMode::Colormap,
+        }
+    }
+
+    // A reasonable value range for the gradient in this mode
+    pub fn default_range(&self, num_balls: usize, world: Rect) -> (f32, f32) {
+        match self {
+            ColorMode::Colormap => (0., 1.),
+            ColorMode::Velocity => (0., 2.),
+            ColorMode::Collide => (0., 6.),
+            ColorMode::Index => (0., num_balls.max(1) as f32),
+            ColorMode::Pressure => (0., 2.),
+            ColorMode::Height => (world.bottom(), world.top()),
+        }
+    }
+
+    fn needs_contacts(&self) -> bool {
+        matches!(self, ColorMode::Collide | ColorMode::Pressure)
+    }
+}
+
+// Balls put back at the start of every run as they were when added, so each run
+// simulates the same world
+#[derive(Default)]
+pub struct Setup {
+    pub balls: Vec<Ball>,
+    // Constraints between the setup's balls, indexed into `balls`
+    pub links: Vec<Link>,
+    pub areas: Vec<AreaConstraint>,
+    pub clusters: Vec<RigidCluster>,
+}
+
+impl Setup {
+    // Forgets the balls with these ids and whatever holds them
+    fn remove(&mut self, ids: &[u64]) {
+        let removed: Vec<usize> = (0..self.balls.len())
+            .filter(|i| ids.contains(&self.balls[*i].id))
+            .collect();
+        self.balls.retain(|ball| !ids.contains(&ball.id));
+        remove_links(&mut self.links, &removed);
+        remove_areas(&mut self.areas, &removed);
+        remove_from_clusters(&mut self.clusters, &removed);
+    }
+}
+
+// Drops the links to the balls at the sorted indices in `removed` and renumbers the rest
+// to match the shifted indices
+fn remove_links(links: &mut Vec<Link>, removed: &[usize]) {
+    let gone = |i: usize| removed.binary_search(&i).is_ok();
+    let renumber = |i: usize| i - removed.partition_point(|r| *r < i);
+    links.retain(|link| !gone(link.a) && !link.other().is_some_and(gone));
+    for link in links.iter_mut() {
+        link.a = renumber(link.a);
+        if let Anchor::Ball(b) = &mut link.b {
+            *b = renumber(*b);
+        }
+    }
+}
+
+// Same for area constraints, which go if any of their corners does
+fn remove_areas(areas: &mut Vec<AreaConstraint>, removed: &[usize]) {
+    let gone = |i: usize| removed.binary_search(&i).is_ok();
+    let renumber = |i: usize| i - removed.partition_point(|r| *r < i);
+    areas.retain(|area| !area.balls.iter().any(|i| gone(*i)));
+    for area in areas.iter_mut() {
+        for i in area.balls.iter_mut() {
+            *i = renumber(*i);
+        }
+    }
+}
+
+// Clusters carry on without their removed members, until fewer than two are left
+fn remove_from_clusters(clusters: &mut Vec<RigidCluster>, removed: &[usize]) {
+    let renumber = |i: usize| i - removed.partition_point(|r| *r < i);
+    for cluster in clusters.iter_mut() {
+        for i in removed {
+            cluster.remove(*i);
+        }
+        for i in cluster.balls.iter_mut() {
+            *i = renumber(*i);
+        }
+    }
+    clusters.retain(|cluster| cluster.balls.len() > 1);
 }
 
 pub struct Solver {
     pub gravity: Vec2,
     pub balls: Vec<RefCell<Ball>>,
     pub boundaries: Vec<Box<dyn Boundary>>,
+    // Index of the boundary the viewer moves to the mouse, if any
+    pub mouse_boundary: Option<usize>,
+    // Distance constraints, solved after collisions every substep
+    pub links: Vec<Link>,
+    // Solved after the links
+    pub areas: Vec<AreaConstraint>,
+    // Solved last, so whatever the other constraints did they leave with their shape
+    pub clusters: Vec<RigidCluster>,
     pub substeps: usize,
     pub hash: SpatialHash,
     pub detect_mode: DetectMode,
     pub colormap: Vec<Rgba>,
-    pub pixel_scale: f32,
+    // One baked colormap per frame of an image sequence, indexed by spawn order
+    pub colormap_frames: Vec<Vec<Rgba>>,
+    // World rectangle the colour image is stretched over
+    pub image_rect: Rect,
+    pub sensors: Vec<Sensor>,
+    // Sensor entries and exits from the last call to `update`
+    pub sensor_events: Vec<SensorEvent>,
+    // Contacts from the last call to `update`, recorded only while the colour mode or
+    // `contacts_wanted` needs them and `None` otherwise
+    pub contacts: Option<ContactLog>,
+    // Set while something outside the solver, such as an overlay, reads `contacts`
+    pub contacts_wanted: bool,
+    pub color_mode: ColorMode,
+    pub palette: Palette,
+    // Values mapped to the two ends of `palette`
+    pub color_range: (f32, f32),
+    pub image_sampling: ImageSampling,
+    // Palette that image colours are snapped to, if any
+    pub quantize: Option<Palette>,
+    pub dither: bool,
+    // Timings and totals from the last call to `update`
+    pub stats: FrameStats,
+    // Calls to `update` so far in this run, advancing a fraction each substep. Kinematic
+    // paths are timed by it.
+    pub time: f32,
+    // What `restart` puts back
+    pub setup: Setup,
 }
 
 impl Solver {
     pub fn update(&mut self, dt: f32) {
         let subdt = dt / (self.substeps as f32);
+        self.sensor_events.clear();
+        if !self.contacts_wanted && !self.color_mode.needs_contacts() {
+            self.contacts = None;
+        } else if self.contacts.is_none() {
+            self.contacts = Some(ContactLog::default());
+        }
+        if let Some(log) = &mut self.contacts {
+            log.clear(self.balls.len());
+        }
+        self.stats = FrameStats::default();
         for _ in 0..self.substeps {
+            let now = Instant::now();
             self.apply_gravity();
+            self.stats.add(Phase::Forces, now);
+            let now = Instant::now();
             self.apply_boundaries();
+            self.stats.add(Phase::Boundaries, now);
             match self.detect_mode {
                 DetectMode::SpatialPartition => self.solve_grid_collisions(),
-                DetectMode::Slow => self.solve_collisions(),
+                DetectMode::Slow => {
+                    let now = Instant::now();
+                    self.solve_collisions();
+                    self.stats.add(Phase::Narrow, now);
+                }
             }
+            let now = Instant::now();
+            self.solve_links();
+            self.solve_areas();
+            self.solve_clusters();
+            self.stats.add(Phase::Constraints, now);
+            let now = Instant::now();
+            self.time += 1. / self.substeps as f32;
             self.update_positions(subdt);
+            self.wrap_positions();
+            self.stats.add(Phase::Integration, now);
+            self.apply_sensors();
+        }
+        self.apply_colors();
+
+        self.stats.kinetic_energy = self
+            .balls
+            .iter()
+            .map(|ball| {
+                let ball = ball.borrow();
+                0.5 * ball.radius.powi(2) * (ball.pos - ball.prev_pos).length_squared()
+            })
+            .sum();
+        if let Some(log) = &self.contacts {
+            self.stats.record_contacts(log);
+        }
+    }
+
+    // Everything that affects where balls settle, one line per setting or shape
+    pub fn describe(&self) -> String {
+        let mut lines = vec![
+            format!("gravity={},{}", self.gravity.x, self.gravity.y),
+            format!("substeps={}", self.substeps),
+            format!("detect_mode={:?}", self.detect_mode),
+            format!("periodic={}", self.hash.periodic.name()),
+        ];
+        // The mouse shape is written at the origin, since its position changes every frame
+        lines.extend(self.boundaries.iter().enumerate().map(|(i, b)| {
+            if self.mouse_boundary == Some(i) {
+                let line = b.describe();
+                let words: Vec<&str> = line
+                    .split_whitespace()
+                    .map(|word| {
+                        if word.starts_with("pos=") {
+                            "pos=0,0"
+                        } else {
+                            word
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("mouse {}", words.join(" "))
+            } else {
+                b.describe()
+            }
+        }));
+        lines.extend(self.sensors.iter().map(|s| s.describe()));
+        // Balls that aren't dynamic or are held by constraints are saved with the
+        // constraints, which refer to them by their place in the saved list
+        let mut saved: Vec<usize> = self
+            .links
+            .iter()
+            .flat_map(|link| std::iter::once(link.a).chain(link.other()))
+            .chain(
+                self.areas
+                    .iter()
+                    .flat_map(|area| area.balls.iter().copied()),
+            )
+            .chain(self.clusters.iter().flat_map(|c| c.balls.iter().copied()))
+            .chain(
+                self.balls
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, ball)| ball.borrow().motion != Motion::Dynamic)
+                    .map(|(i, _)| i),
+            )
+            .collect();
+        saved.sort_unstable();
+        saved.dedup();
+        let place = |i: usize| saved.binary_search(&i).unwrap();
+        lines.extend(saved.iter().map(|i| self.balls[*i].borrow().describe()));
+        lines.extend(self.links.iter().map(|link| {
+            let b = match link.b {
+                Anchor::Ball(b) => Anchor::Ball(place(b)),
+                anchor => anchor,
+            };
+            link.describe(place(link.a), b)
+        }));
+        let places = |balls: &[usize]| balls.iter().map(|i| place(*i)).collect::<Vec<_>>();
+        lines.extend(
+            self.areas
+                .iter()
+                .map(|area| area.describe(&places(&area.balls))),
+        );
+        lines.extend(self.clusters.iter().map(|c| c.describe(&places(&c.balls))));
+        lines.join("\n")
+    }
+
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.color_mode = mode;
+        self.color_range = mode.default_range(self.balls.len(), self.hash.bounds);
+        if mode == ColorMode::Colormap {
+            for ball in self.balls.iter() {
+                let mut ball = ball.borrow_mut();
+                if let Some(color) = self.colormap.get(ball.spawn_index) {
+                    ball.color = Hsv::from(*color);
+                }
+            }
+        }
+    }
+
+    fn apply_colors(&mut self) {
+        for (i, ball) in self.balls.iter().enumerate() {
+            let mut ball = ball.borrow_mut();
+            let value = match self.color_mode {
+                ColorMode::Colormap => return,
+                ColorMode::Velocity => (ball.pos - ball.prev_pos).length(),
+                ColorMode::Collide => self
+                    .contacts
+                    .as_ref()
+                    .and_then(|log| log.counts.get(i))
+                    .map_or(0., |c| *c as f32),
+                ColorMode::Index => ball.spawn_index as f32,
+                ColorMode::Pressure => self
+                    .contacts
+                    .as_ref()
+                    .and_then(|log| log.pressure.get(i))
+                    .map_or(0., |p| *p),
+                ColorMode::Height => ball.pos.y,
+            };
+            ball.color = Hsv::from(self.palette.map(value, self.color_range));
+        }
+    }
+
+    pub fn add_sensor(&mut self, sensor: Sensor) {
+        assert!(
+            self.sensors.len() < MAX_SENSORS,
+            "at most {} sensors are supported",
+            MAX_SENSORS
+        );
+        self.sensors.push(sensor);
+    }
+
+    fn apply_sensors(&mut self) {
+        let mut removed = vec![];
+        for (i, ball) in self.balls.iter().enumerate() {
+            let mut ball = ball.borrow_mut();
+            for (s, sensor) in self.sensors.iter_mut().enumerate() {
+                let bit = 1 << s;
+                let was_inside = ball.sensors & bit != 0;
+                let inside = sensor.contains(&ball);
+                if inside && !was_inside {
+                    ball.sensors |= bit;
+                    self.sensor_events.push(SensorEvent {
+                        sensor: s,
+                        ball: Some(i),
+                        kind: SensorEventKind::Enter,
+                    });
+                    if sensor.trigger(&mut ball) {
+                        removed.push(i);
+                        break;
+                    }
+                } else if !inside && was_inside {
+                    ball.sensors &= !bit;
+                    self.sensor_events.push(SensorEvent {
+                        sensor: s,
+                        ball: Some(i),
+                        kind: SensorEventKind::Exit,
+                    });
+                }
+            }
+        }
+
+        self.remove_indices(&removed);
+    }
+
+    // Colours are keyed by `spawn_index`, so the colormap stays valid as balls go.
+    // Removed fixtures aren't put back by `restart` either.
+    pub fn remove_balls<F: Fn(&Ball) -> bool>(&mut self, remove: F) {
+        let removed: Vec<usize> = self
+            .balls
+            .iter()
+            .enumerate()
+            .filter(|(_, ball)| remove(&ball.borrow()))
+            .map(|(i, _)| i)
+            .collect();
+        let ids: Vec<u64> = removed.iter().map(|i| self.balls[*i].borrow().id).collect();
+        self.setup.remove(&ids);
+        self.remove_indices(&removed);
+    }
+
+    // `removed` must be sorted. Links, sensor events and contacts of removed balls go
+    // with them and the rest are renumbered to match the shifted indices.
+    fn remove_indices(&mut self, removed: &[usize]) {
+        if removed.is_empty() {
+            return;
         }
+        let mut i = 0;
+        self.balls.retain(|_| {
+            let keep = removed.binary_search(&i).is_err();
+            i += 1;
+            keep
+        });
+        let gone = |i: usize| removed.binary_search(&i).is_ok();
+        let renumber = |i: usize| i - removed.partition_point(|r| *r < i);
+        for event in self.sensor_events.iter_mut() {
+            event.ball = event.ball.filter(|i| !gone(*i)).map(renumber);
+        }
+        if let Some(log) = &mut self.contacts {
+            log.remove_indices(removed);
+        }
+        remove_links(&mut self.links, removed);
+        remove_areas(&mut self.areas, removed);
+        remove_from_clusters(&mut self.clusters, removed);
+    }
+
+    // Links pinned to the boundary go with it, in this run and later ones. Returns the
+    // shape, so it can be reused.
+    pub fn remove_boundary(&mut self, index: usize) -> Box<dyn Boundary> {
+        let bound = self.boundaries.remove(index);
+        self.mouse_boundary = match self.mouse_boundary {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            other => other,
+        };
+        for links in [&mut self.links, &mut self.setup.links] {
+            links.retain(|link| !matches!(link.b, Anchor::Boundary { index: i, .. } if i == index));
+            for link in links.iter_mut() {
+                if let Anchor::Boundary { index: i, .. } = &mut link.b {
+                    if *i > index {
+                        *i -= 1;
+                    }
+                }
+            }
+        }
+        bound
+    }
+
+    // World position of a link's anchor, or `None` if what it was attached to is gone.
+    // Balls across a periodic seam give the position of their ghost next to `from`.
+    pub fn anchor_pos(&self, anchor: &Anchor, from: Vec2) -> Option<Vec2> {
+        match *anchor {
+            Anchor::Ball(b) => self.balls.get(b).map(|ball| {
+                let pos = ball.borrow().pos;
+                pos + self.hash.ghost_offset(from, pos)
+            }),
+            Anchor::Point(point) => Some(point),
+            Anchor::Boundary { index, offset } => {
+                self.boundaries.get(index).map(|bound| bound.pos() + offset)
+            }
+        }
+    }
+
+    // Place in `setup.balls` of the ball at `index`, if it is a fixture
+    fn setup_index(&self, index: usize) -> Option<usize> {
+        let id = self.balls.get(index)?.borrow().id;
+        self.setup.balls.iter().position(|ball| ball.id == id)
+    }
+
+    // Adds a link, which every later run starts with too if the balls it joins do
+    pub fn add_link(&mut self, link: Link) {
+        let a = self.setup_index(link.a);
+        let b = match link.b {
+            Anchor::Ball(b) => self.setup_index(b).map(Anchor::Ball),
+            anchor => Some(anchor),
+        };
+        if let (Some(a), Some(b)) = (a, b) {
+            self.setup.links.push(Link { a, b, ..link });
+        }
+        self.links.push(link);
+    }
+
+    // Adds an area constraint, which every later run starts with too if its corners do
+    pub fn add_area(&mut self, area: AreaConstraint) {
+        let corners: Option<Vec<usize>> = area.balls.iter().map(|i| self.setup_index(*i)).collect();
+        if let Some(balls) = corners {
+            self.setup.areas.push(AreaConstraint {
+                balls,
+                ..area.clone()
+            });
+        }
+        self.areas.push(area);
+    }
+
+    // Holds a ball where it is
+    pub fn pin_to_point(&mut self, ball: usize) {
+        let pos = self.balls[ball].borrow().pos;
+        self.add_link(Link::new(ball, Anchor::Point(pos), 0., LinkKind::Rigid));
+    }
+
+    // Carries a ball along with a boundary, keeping its offset from the boundary's
+    // position
+    pub fn pin_to_boundary(&mut self, ball: usize, boundary: usize) {
+        let offset = self.balls[ball].borrow().pos - self.boundaries[boundary].pos();
+        self.add_link(Link::new(
+            ball,
+            Anchor::Boundary {
+                index: boundary,
+                offset,
+            },
+            0.,
+            LinkKind::Rigid,
+        ));
+    }
+
+    // Adds `count` balls evenly spaced from `start` to `end`, each linked to the next, and
+    // returns their indices. Spacing under twice the radius leaves neighbours pushing
+    // against their link.
+    pub fn add_chain(
+        &mut self,
+        start: Vec2,
+        end: Vec2,
+        count: usize,
+        radius: f32,
+        color: Hsv,
+        kind: LinkKind,
+    ) -> Vec<usize> {
+        let step = (end - start) / (count.max(2) - 1) as f32;
+        let first = self.balls.len();
+        for i in 0..count {
+            let pos = start + step * i as f32;
+            let mut ball = Ball::new(pos, pos, radius, color);
+            ball.spawn_index = PAINTED;
+            self.add_fixture(ball);
+        }
+        for i in first + 1..first + count {
+            self.add_link(Link::new(i - 1, Anchor::Ball(i), step.length(), kind));
+        }
+        (first..first + count).collect()
+    }
+
+    // Snapped links are removed, along with the area constraints that relied on them
+    fn solve_links(&mut self) {
+        let mut snapped = vec![];
+        for (i, link) in self.links.iter().enumerate() {
+            let mut a = match self.balls.get(link.a) {
+                Some(a) => a.borrow_mut(),
+                None => continue,
+            };
+            let held = match link.b {
+                Anchor::Ball(b) => {
+                    if b == link.a {
+                        continue;
+                    }
+                    match self.balls.get(b) {
+                        Some(b) => {
+                            let mut b = b.borrow_mut();
+                            let ghost = self.hash.ghost_offset(a.pos, b.pos);
+                            b.pos += ghost;
+                            b.prev_pos += ghost;
+                            let held = link.solve_pair(&mut a, &mut b);
+                            b.pos -= ghost;
+                            b.prev_pos -= ghost;
+                            held
+                        }
+                        None => true,
+                    }
+                }
+                Anchor::Point(point) => link.solve_pin(&mut a, point),
+                Anchor::Boundary { index, offset } => match self.boundaries.get(index) {
+                    Some(bound) => link.solve_pin(&mut a, bound.pos() + offset),
+                    None => true,
+                },
+            };
+            if !held {
+                snapped.push(i);
+            }
+        }
+
+        for i in snapped.into_iter().rev() {
+            let link = self.links.remove(i);
+            if let Some(b) = link.other() {
+                self.areas
+                    .retain(|area| !(area.balls.contains(&link.a) && area.balls.contains(&b)));
+            }
+        }
+    }
+
+    // Positions of `balls`, moved across any periodic seam to sit next to the first one.
+    // `None` if any of them is gone.
+    fn gather(&self, balls: &[usize]) -> Option<Vec<Vec2>> {
+        let first = self.balls.get(*balls.first()?)?.borrow().pos;
+        balls
+            .iter()
+            .map(|i| {
+                self.balls.get(*i).map(|ball| {
+                    let pos = ball.borrow().pos;
+                    pos + self.hash.ghost_offset(first, pos)
+                })
+            })
+            .collect()
+    }
+
+    // Moves each ball by how far its gathered position was moved. Fixed and kinematic
+    // balls stay put.
+    fn scatter(&self, balls: &[usize], before: &[Vec2], after: &[Vec2]) {
+        for (i, (before, after)) in balls.iter().zip(before.iter().zip(after)) {
+            let mut ball = self.balls[*i].borrow_mut();
+            let mobility = ball.mobility();
+            ball.pos += (*after - *before) * mobility;
+        }
+    }
+
+    fn solve_areas(&mut self) {
+        for area in self.areas.iter() {
+            if let Some(before) = self.gather(&area.balls) {
+                let mut after = before.clone();
+                area.solve(&mut after);
+                self.scatter(&area.balls, &before, &after);
+            }
+        }
+    }
+
+    fn solve_clusters(&mut self) {
+        for cluster in self.clusters.iter() {
+            if let Some(before) = self.gather(&cluster.balls) {
+                let mut after = before.clone();
+                cluster.solve(&mut after);
+                self.scatter(&cluster.balls, &before, &after);
+            }
+        }
+    }
+
+    // Groups existing balls into a rigid cluster in their current arrangement. Returns
+    // false, adding nothing, if any of them is missing or isn't dynamic.
+    pub fn make_rigid(&mut self, balls: Vec<usize>) -> bool {
+        match self.gather(&balls) {
+            Some(positions) => {
+                let radii: Vec<f32> = balls
+                    .iter()
+                    .map(|i| self.balls[*i].borrow().radius)
+                    .collect();
+                self.add_rigid(RigidCluster::new(balls, &positions, &radii))
+            }
+            None => false,
+        }
+    }
+
+    // Adds a rigid cluster, which every later run starts with too if its members do.
+    // Returns false, adding nothing, if any member is missing or isn't dynamic, since
+    // those can't be moved into place with the rest.
+    pub fn add_rigid(&mut self, cluster: RigidCluster) -> bool {
+        let dynamic = cluster.balls.iter().all(|i| {
+            self.balls
+                .get(*i)
+                .is_some_and(|ball| ball.borrow().motion == Motion::Dynamic)
+        });
+        if !dynamic {
+            return false;
+        }
+        let members: Option<Vec<usize>> =
+            cluster.balls.iter().map(|i| self.setup_index(*i)).collect();
+        if let Some(balls) = members {
+            self.setup.clusters.push(RigidCluster {
+                balls,
+                ..cluster.clone()
+            });
+        }
+        self.clusters.push(cluster);
+        true
+    }
+
+    // Adds balls at `offsets` from `center`, turned by `angle`, as one rigid cluster and
+    // returns their indices
+    pub fn add_cluster(
+        &mut self,
+        center: Vec2,
+        angle: f32,
+        offsets: &[Vec2],
+        radius: f32,
+        color: Hsv,
+    ) -> Vec<usize> {
+        let first = self.balls.len();
+        for offset in offsets {
+            let pos = center + offset.rotate(angle);
+            let mut ball = Ball::new(pos, pos, radius, color);
+            ball.spawn_index = PAINTED;
+            self.add_fixture(ball);
+        }
+        let balls: Vec<usize> = (first..self.balls.len()).collect();
+        self.make_rigid(balls.clone());
+        balls
+    }
+
+    // Adds a ball at each lattice point, linked along the triangle sides, and returns
+    // their indices in the order of `Lattice::points`
+    pub fn add_soft_body(&mut self, lattice: &Lattice, body: &SoftBody) -> Vec<usize> {
+        let first = self.balls.len();
+        for pos in lattice.points.iter() {
+            let mut ball = Ball::new(*pos, *pos, body.ball_radius, body.color);
+            ball.spawn_index = PAINTED;
+            self.add_fixture(ball);
+        }
+        for (a, b) in lattice.edges() {
+            let length = (lattice.points[b] - lattice.points[a]).length();
+            let mut link = Link::new(first + a, Anchor::Ball(first + b), length, body.link);
+            link.max_strain = body.max_strain;
+            self.add_link(link);
+        }
+        if body.area_stiffness > 0. {
+            for triangle in lattice.triangles.iter() {
+                let corners: Vec<Vec2> = triangle.iter().map(|i| lattice.points[*i]).collect();
+                self.add_area(AreaConstraint {
+                    balls: triangle.iter().map(|i| first + i).collect(),
+                    area: signed_area(&corners),
+                    stiffness: body.area_stiffness,
+                });
+            }
+        }
+        (first..first + lattice.points.len()).collect()
     }
 
     pub fn set_image_colors(&mut self, image: &mut DynamicImage) {
-        let pixel_size_real = self.pixel_scale;
         let image = image.to_rgba8();
-        let width_real = image.width() as f32 * pixel_size_real;
-        let height_real = image.height() as f32 * pixel_size_real;
-        let num_x_pixels = image.width();
-        let num_y_pixels = image.height();
-        let pixel_size_real = pixel_size_real as f32;
-
-        for i in 0..self.balls.len() {
-            let mut ball = self.balls[i].borrow_mut();
-            // Get relative to corner an index based on the pixel size
-            let mut py = (((height_real / 2.0) - ball.pos.y) / pixel_size_real) as usize;
-            let mut px = (((width_real / 2.0) + ball.pos.x) / pixel_size_real) as usize;
-            px = px.clamp(0, (num_x_pixels - 1) as usize);
-            py = py.clamp(0, (num_y_pixels - 1) as usize);
-            let rgba = image.get_pixel(px as u32, py as u32);
-            let r = rgba[0] as f32 / 255.;
-            let g = rgba[1] as f32 / 255.;
-            let b = rgba[2] as f32 / 255.;
-            let a = rgba[3] as f32 / 255.;
-            // println!("{} {} {} {}", r, g, b, a);
-            ball.color = Hsv::from(Rgb::new(r, g, b));
-            self.colormap[i] = Rgba::new(r, g, b, a);
+        let mut colors: Vec<Rgba> = self
+            .balls
+            .iter()
+            .map(|ball| {
+                let ball = ball.borrow();
+                sample_image(
+                    &image,
+                    self.image_rect,
+                    ball.pos,
+                    ball.radius,
+                    self.image_sampling,
+                )
+            })
+            .collect();
+
+        if let Some(palette) = &self.quantize {
+            let positions: Vec<(Vec2, f32)> = self
+                .balls
+                .iter()
+                .map(|ball| (ball.borrow().pos, ball.borrow().radius))
+                .collect();
+            quantize(&positions, &mut colors, &palette.colors(), self.dither);
+        }
+
+        for (ball, color) in self.balls.iter().zip(colors) {
+            let mut ball = ball.borrow_mut();
+            ball.color = Hsv::from(color);
+            if let Some(slot) = self.colormap.get_mut(ball.spawn_index) {
+                *slot = color;
+            }
         }
     }
 
+    // Colours the settled balls from one frame of a sequence and keeps the result
+    pub fn bake_colormap_frame(&mut self, frame: usize, image: &mut DynamicImage) {
+        self.set_image_colors(image);
+        if self.colormap_frames.len() <= frame {
+            self.colormap_frames.resize(frame + 1, vec![]);
+        }
+        self.colormap_frames[frame] = self.colormap.clone();
+    }
+
+    // Makes a baked frame the active colormap and recolours the balls from it
+    pub fn show_colormap_frame(&mut self, frame: usize) {
+        if let Some(colors) = self.colormap_frames.get(frame) {
+            self.colormap.clone_from(colors);
+            for ball in self.balls.iter() {
+                let mut ball = ball.borrow_mut();
+                if let Some(color) = self.colormap.get(ball.spawn_index) {
+                    ball.color = Hsv::from(*color);
+                }
+            }
+        }
+    }
+
+    // Starts the run again from the setup. Spawned and painted balls go, fixtures go back
+    // to where they were added and kinematic balls to the start of their paths.
     pub fn restart(&mut self) {
         for cell in self.hash.grid.iter_mut() {
             // cell.truncate(0);
             cell.clear();
         }
-        self.balls.clear();
+        self.time = 0.;
+        self.balls = self
+            .setup
+            .balls
+            .iter()
+            .map(|ball| {
+                let mut ball = ball.clone();
+                if let Motion::Kinematic(path) = &ball.motion {
+                    ball.pos = path.at(0.);
+                    ball.prev_pos = ball.pos;
+                }
+                RefCell::new(ball)
+            })
+            .collect();
+        self.links.clone_from(&self.setup.links);
+        self.areas.clone_from(&self.setup.areas);
+        self.clusters.clone_from(&self.setup.clusters);
     }
 
     fn check_cell_collisions(&mut self, cell_1_idx: (usize, usize), cell_2_idx: (usize, usize)) {
         // Loop over indicies to check for collisions in this kernel
+        // Across a periodic seam the other ball is moved to its ghost position next to
+        // this one for the check, then moved back
 
         unsafe {
             let cell_1 = self.hash.grid.get_unchecked(cell_1_idx.0, cell_1_idx.1);
@@ -93,10 +810,25 @@ impl Solver {
                     if current_idx != other_idx {
                         let mut current_ball = self.balls.get_unchecked(*current_idx).borrow_mut();
                         let mut other_ball = self.balls.get_unchecked(*other_idx).borrow_mut();
-                        if Ball::detect_pair_collide(&current_ball, &other_ball) {
+                        let ghost = self.hash.ghost_offset(current_ball.pos, other_ball.pos);
+                        other_ball.pos += ghost;
+                        other_ball.prev_pos += ghost;
+                        if current_ball.filter.interacts(&other_ball.filter)
+                            && Ball::detect_pair_collide(&current_ball, &other_ball)
+                        {
                             // did_collide = true;
+                            if let Some(log) = &mut self.contacts {
+                                log.record_pair(
+                                    *current_idx,
+                                    *other_idx,
+                                    &current_ball,
+                                    &other_ball,
+                                );
+                            }
                             Ball::resolve_pair_collide(&mut current_ball, &mut other_ball);
                         }
+                        other_ball.pos -= ghost;
+                        other_ball.prev_pos -= ghost;
                     }
                 }
                 // if !did_collide {
@@ -109,64 +841,189 @@ impl Solver {
         }
     }
 
+    fn collide_pair(&mut self, i: usize, j: usize) {
+        let mut current = self.balls[i].borrow_mut();
+        let mut other = self.balls[j].borrow_mut();
+        let ghost = self.hash.ghost_offset(current.pos, other.pos);
+        other.pos += ghost;
+        other.prev_pos += ghost;
+        if current.filter.interacts(&other.filter) && Ball::detect_pair_collide(&current, &other) {
+            if let Some(log) = &mut self.contacts {
+                log.record_pair(i, j, &current, &other);
+            }
+            Ball::resolve_pair_collide(&mut current, &mut other);
+        }
+        other.pos -= ghost;
+        other.prev_pos -= ghost;
+    }
+
     fn solve_grid_collisions(&mut self) {
+        let now = Instant::now();
         // Insert into grid
         for cell in self.hash.grid.iter_mut() {
             cell.clear();
         }
 
-        // Hash the balls
+        // Hash the balls. Balls too big for a cell would reach past the neighbouring
+        // cells, so they're kept out of the grid and checked against every ball instead.
+        let max_radius = self.hash.resolution / 2.;
+        let mut oversized = vec![];
         self.balls.iter().enumerate().for_each(|(i, ball)| {
-            self.hash.hash(ball.borrow().pos, i);
+            let ball = ball.borrow();
+            if ball.radius > max_radius {
+                oversized.push(i);
+            } else {
+                self.hash.hash(ball.pos, i);
+            }
         });
 
+        self.stats.add(Phase::HashBuild, now);
+
         // Detect collisions
+        let now = Instant::now();
         let (rows, cols) = self.hash.grid.size();
-        for cr in 1..rows - 1 {
-            for cc in 1..cols - 1 {
+        // Edge cells are only checked from their inner neighbours, unless their axis
+        // wraps and they have neighbours across the seam
+        let periodic = self.hash.periodic;
+        let row_range = if periodic.y { 0..rows } else { 1..rows - 1 };
+        let col_range = if periodic.x { 0..cols } else { 1..cols - 1 };
+        // A wrapping axis with only one or two cells reaches the same cell from more
+        // than one side, so each neighbour is checked once
+        let mut neighbours = Vec::with_capacity(9);
+        for cr in row_range {
+            for cc in col_range.clone() {
                 // Loop around each cell
                 let current_cell_idx = (cr, cc);
-                for i in 0..3 {
-                    for j in 0..3 {
-                        // True index
-                        let current_row = cr + i - 1;
-                        let current_col = cc + j - 1;
-                        // Clamp to valid index
-                        let current_row = current_row.clamp(0, rows);
-                        let current_col = current_col.clamp(0, cols);
-
-                        // Get other ball and check
-                        let other_cell_idx = (current_row, current_col);
-                        let other_cell = self.hash.grid.get(current_row, current_col).unwrap();
-                        self.check_cell_collisions(current_cell_idx, other_cell_idx);
+                neighbours.clear();
+                for i in -1..=1 {
+                    for j in -1..=1 {
+                        if let Some(other_cell_idx) = self.hash.neighbour(cr, cc, (i, j)) {
+                            if !neighbours.contains(&other_cell_idx) {
+                                neighbours.push(other_cell_idx);
+                            }
+                        }
                     }
                 }
+                for &other_cell_idx in neighbours.iter() {
+                    self.check_cell_collisions(current_cell_idx, other_cell_idx);
+                }
+            }
+        }
+        for (n, &i) in oversized.iter().enumerate() {
+            for j in 0..self.balls.len() {
+                // Pairs of oversized balls are checked once, from the first of them
+                if j != i && !oversized[..n].contains(&j) {
+                    self.collide_pair(i, j);
+                }
             }
         }
+        self.stats.add(Phase::Narrow, now);
     }
 
     // println!("Time collide {}", 1. / now.elapsed().as_secs_f32());
     fn update_positions(&mut self, dt: f32) {
-        self.balls
-            .iter_mut()
-            .for_each(|x| x.borrow_mut().update(dt));
+        self.balls.iter_mut().for_each(|x| {
+            let mut ball = x.borrow_mut();
+            ball.update(dt);
+            ball.drive(self.time);
+        });
+    }
+
+    // Adds a ball that every later run starts with too, and returns its index
+    pub fn add_fixture(&mut self, ball: Ball) -> usize {
+        self.setup.balls.push(ball.clone());
+        self.balls.push(RefCell::new(ball));
+        self.balls.len() - 1
+    }
+
+    // A ball that never moves and that dynamic balls can't push
+    pub fn add_peg(&mut self, pos: Vec2, radius: f32, color: Hsv) -> usize {
+        let mut ball = Ball::new(pos, pos, radius, color);
+        ball.spawn_index = PAINTED;
+        ball.motion = Motion::Fixed;
+        self.add_fixture(ball)
+    }
+
+    // A ball that follows `path` from the current time, pushing dynamic balls aside
+    pub fn add_kinematic(&mut self, path: Path, radius: f32, color: Hsv) -> usize {
+        let pos = path.at(self.time);
+        let mut ball = Ball::new(pos, pos, radius, color);
+        ball.spawn_index = PAINTED;
+        ball.motion = Motion::Kinematic(path);
+        self.add_fixture(ball)
+    }
+
+    // Rows of pegs for a Galton board, one more in each row going down from `top`, with
+    // `spacing` between neighbouring pegs
+    pub fn add_galton_pegs(
+        &mut self,
+        top: Vec2,
+        rows: usize,
+        spacing: f32,
+        radius: f32,
+        color: Hsv,
+    ) -> Vec<usize> {
+        let row_height = spacing * 3f32.sqrt() / 2.;
+        let mut pegs = vec![];
+        for row in 0..rows {
+            for i in 0..=row {
+                let offset = Vec2::new(
+                    (i as f32 - row as f32 / 2.) * spacing,
+                    -(row as f32) * row_height,
+                );
+                pegs.push(self.add_peg(top + offset, radius, color));
+            }
+        }
+        pegs
+    }
+
+    // Balls that left the world along a periodic axis come back on the other side
+    // with the same velocity
+    fn wrap_positions(&mut self) {
+        if self.hash.periodic == Periodic::default() {
+            return;
+        }
+        for ball in self.balls.iter() {
+            let mut ball = ball.borrow_mut();
+            let offset = self.hash.wrap_offset(ball.pos);
+            ball.pos += offset;
+            ball.prev_pos += offset;
+        }
     }
 
     fn apply_boundaries(&mut self) {
         // for _ in 0..self.substeps {
         self.boundaries.iter().for_each(|bound| {
-            self.balls.iter_mut().for_each(|ball| match bound.kind() {
-                BoundaryType::Inner => {
-                    if bound.detect_inner_collision(&mut ball.borrow_mut()) {
-                        bound.apply_inner_constraint(&mut ball.borrow_mut())
-                    }
-                }
-                BoundaryType::Outer => {
-                    if bound.detect_outer_collision(&mut ball.borrow_mut()) {
-                        bound.apply_outer_constraint(&mut ball.borrow_mut())
+            let filter = bound.filter();
+            self.balls
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, ball)| {
+                    let ball = ball.borrow();
+                    ball.motion == Motion::Dynamic && filter.interacts(&ball.filter)
+                })
+                .for_each(|(i, ball)| {
+                    let before = ball.borrow().pos;
+                    let collided = match bound.kind() {
+                        BoundaryType::Inner => {
+                            let collided = bound.detect_inner_collision(&mut ball.borrow_mut());
+                            if collided {
+                                bound.apply_inner_constraint(&mut ball.borrow_mut())
+                            }
+                            collided
+                        }
+                        BoundaryType::Outer => {
+                            let collided = bound.detect_outer_collision(&mut ball.borrow_mut());
+                            if collided {
+                                bound.apply_outer_constraint(&mut ball.borrow_mut())
+                            }
+                            collided
+                        }
+                    };
+                    if let (true, Some(log)) = (collided, &mut self.contacts) {
+                        log.record_boundary(i, before, &ball.borrow());
                     }
-                }
-            })
+                })
         });
         // }
     }
@@ -181,21 +1038,35 @@ impl Solver {
     }
 
     pub fn solve_collisions(&mut self) {
-        for _ in 0..self.substeps {
+        for pass in 0..self.substeps {
             for i in 0..self.balls.len() {
                 let (before, since) = self.balls.split_at_mut(i);
                 let (mut current, after) = since.split_first_mut().unwrap();
                 let mut did_collide = false;
-                for mut other in before.iter_mut().chain(after) {
-                    if Ball::detect_pair_collide(&current.borrow_mut(), &other.borrow_mut()) {
+                for (j, mut other) in before.iter_mut().chain(after).enumerate() {
+                    let ghost = self
+                        .hash
+                        .ghost_offset(current.borrow().pos, other.borrow().pos);
+                    other.borrow_mut().pos += ghost;
+                    other.borrow_mut().prev_pos += ghost;
+                    if current.borrow().filter.interacts(&other.borrow().filter)
+                        && Ball::detect_pair_collide(&current.borrow_mut(), &other.borrow_mut())
+                    {
                         // current.borrow_mut().color = Hsv::new(0., 1., 1.);
                         // other.borrow_mut().color = Hsv::new(0., 1., 1.);
                         did_collide = true;
+                        // Once per substep like the grid, however many passes run
+                        if let (0, Some(log)) = (pass, &mut self.contacts) {
+                            let j = if j < i { j } else { j + 1 };
+                            log.record_pair(i, j, &current.borrow(), &other.borrow());
+                        }
                         Ball::resolve_pair_collide(
                             &mut current.borrow_mut(),
                             &mut other.borrow_mut(),
                         );
                     }
+                    other.borrow_mut().pos -= ghost;
+                    other.borrow_mut().prev_pos -= ghost;
                 }
                 if !did_collide {
                     // current.borrow_mut().color = Hsv::new(0., 0., 1.);
@@ -216,14 +1087,24 @@ impl Solver {
             // .finish();
         });
 
+        for link in self.links.iter() {
+            if let Some(a) = self.balls.get(link.a) {
+                let from = a.borrow().pos;
+                if let Some(to) = self.anchor_pos(&link.b, from) {
+                    draw.line().start(from).end(to).weight(1.).color(GRAY);
+                }
+            }
+        }
+
         self.boundaries.iter().for_each(|bound| bound.draw(draw));
+        self.sensors.iter().for_each(|sensor| sensor.draw(draw));
         // self.hash.draw(draw);
     }
 
     pub fn init_balls(ball_radius: f32) -> Vec<RefCell<Ball>> {
         let mut vec_balls = Vec::<RefCell<Ball>>::new();
         let max = 0; // try 60
-        let hue_step = 360. / ((max * max) as f32);
+        let palette = Palette::rainbow();
         let mut i = 0.;
         let max_radius = ball_radius;
         let min_radius = 3.0;
@@ -237,13 +1118,14 @@ impl Solver {
                 let rand_radius = radius_range * source.read_f64() as f32 + min_radius;
                 let yd =
                     ((y as f32) * 2. * ball_radius) - (max / 2) as f32 * 2. * ball_radius + ypos;
-                vec_balls.push(RefCell::new(Ball {
-                    prev_pos: Vec2::new(xd, yd),
-                    pos: Vec2::new(xd, yd),
-                    radius: ball_radius,
-                    color: Hsv::new(i * hue_step, 1., 1.),
-                    acc: Vec2::ZERO,
-                }));
+                let mut ball = Ball::new(
+                    Vec2::new(xd, yd),
+                    Vec2::new(xd, yd),
+                    ball_radius,
+                    Hsv::from(palette.sample(i / ((max * max) as f32))),
+                );
+                ball.spawn_index = vec_balls.len();
+                vec_balls.push(RefCell::new(ball));
                 i += 1.;
             }
         }
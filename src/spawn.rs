@@ -1,9 +1,16 @@
 use crate::ball::*;
+use crate::palette::*;
 use nannou::prelude::*;
 use std::cell::RefCell;
 
 pub trait Spawner {
     fn set_pos(&mut self, pos: Vec2);
+    fn set_filter(&mut self, filter: CollisionFilter);
+    fn set_palette(&mut self, palette: Palette);
+    fn spawn_velocity(&self) -> f32;
+    fn set_spawn_velocity(&mut self, velocity: f32);
+    fn spawn_period(&self) -> usize;
+    fn set_spawn_period(&mut self, period: usize);
     fn update<D: Fn(f32) -> f32>(
         &mut self,
         ball_vec: &mut Vec<RefCell<Ball>>,
@@ -14,6 +21,9 @@ pub trait Spawner {
         color_map: &mut Vec<Rgba>,
     ) -> u32;
     fn reset(&mut self);
+    fn max_spawn(&self) -> usize;
+    // One line of `name key=value ...` pairs, as for `Boundary::describe`
+    fn describe(&self) -> String;
 }
 
 // pub type DriverFunc = Fn(f32) -> f32;
@@ -27,6 +37,8 @@ pub struct LinearSpawner {
     mirror: bool,
     max_spawn: usize,
     spawn_count: usize,
+    filter: CollisionFilter,
+    palette: Option<Palette>,
 }
 
 impl LinearSpawner {
@@ -48,6 +60,8 @@ impl LinearSpawner {
             mirror,
             max_spawn,
             spawn_count: 0,
+            filter: CollisionFilter::default(),
+            palette: None,
         }
     }
 }
@@ -57,10 +71,55 @@ impl Spawner for LinearSpawner {
         self.pos = pos;
     }
 
+    fn set_filter(&mut self, filter: CollisionFilter) {
+        self.filter = filter;
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = Some(palette);
+    }
+
+    fn spawn_velocity(&self) -> f32 {
+        self.spawn_velocity
+    }
+
+    fn set_spawn_velocity(&mut self, velocity: f32) {
+        self.spawn_velocity = velocity;
+    }
+
+    fn spawn_period(&self) -> usize {
+        self.spawn_period
+    }
+
+    // Periods below one frame would never spawn
+    fn set_spawn_period(&mut self, period: usize) {
+        self.spawn_period = period.max(1);
+    }
+
     fn reset(&mut self) {
         self.spawn_count = 0;
     }
 
+    fn max_spawn(&self) -> usize {
+        self.max_spawn
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "linear pos={},{} angle={} period={} velocity={} rows={} mirror={} max_spawn={} layers={:x} mask={:x}",
+            self.pos.x,
+            self.pos.y,
+            self.angle,
+            self.spawn_period,
+            self.spawn_velocity,
+            self.rows,
+            self.mirror,
+            self.max_spawn,
+            self.filter.layers,
+            self.filter.mask
+        )
+    }
+
     fn update<D: Fn(f32) -> f32>(
         &mut self,
         vec_balls: &mut Vec<RefCell<Ball>>,
@@ -81,8 +140,13 @@ impl Spawner for LinearSpawner {
 
             // Spawing each ball
             for i in 0..self.rows {
-                // By default set color to a hue based on spawn_count
-                let mut color = Rgba::from(Hsv::new(self.spawn_count as f32 * 25. / 360., 1., 1.));
+                // By default set color to a hue based on spawn_count, or spread the palette over the run
+                let mut color = match &self.palette {
+                    Some(palette) => {
+                        palette.sample(self.spawn_count as f32 / self.max_spawn as f32)
+                    }
+                    None => Rgba::from(Hsv::new(self.spawn_count as f32 * 25. / 360., 1., 1.)),
+                };
 
                 let spawn_pos = self.pos - (self.rows as f32 / 2. * spacing * tangent)
                     + (i as f32) * spacing * tangent;
@@ -98,13 +162,15 @@ impl Spawner for LinearSpawner {
 
                 // Push a ball
                 if self.spawn_count < self.max_spawn {
-                    vec_balls.push(RefCell::new(Ball {
-                        prev_pos: spawn_pos,
-                        pos: spawn_pos + self.spawn_velocity * normal,
-                        radius: ball_radius,
-                        color: color_hsv,
-                        acc: Vec2::ZERO,
-                    }));
+                    let mut ball = Ball::new(
+                        spawn_pos + self.spawn_velocity * normal,
+                        spawn_pos,
+                        ball_radius,
+                        color_hsv,
+                    );
+                    ball.filter = self.filter;
+                    ball.spawn_index = self.spawn_count;
+                    vec_balls.push(RefCell::new(ball));
 
                     self.spawn_count += 1;
                     num_spawned_now += 1;
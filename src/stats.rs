@@ -0,0 +1,229 @@
+use crate::contact::*;
+use nannou::prelude::*;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Forces,
+    Boundaries,
+    HashBuild,
+    Narrow,
+    Constraints,
+    Integration,
+    Spawning,
+    Drawing,
+}
+
+pub const PHASES: [Phase; 8] = [
+    Phase::Forces,
+    Phase::Boundaries,
+    Phase::HashBuild,
+    Phase::Narrow,
+    Phase::Constraints,
+    Phase::Integration,
+    Phase::Spawning,
+    Phase::Drawing,
+];
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Forces => "forces",
+            Phase::Boundaries => "boundaries",
+            Phase::HashBuild => "hash build",
+            Phase::Narrow => "narrow phase",
+            Phase::Constraints => "constraints",
+            Phase::Integration => "integration",
+            Phase::Spawning => "spawning",
+            Phase::Drawing => "drawing",
+        }
+    }
+
+    fn color(&self) -> Rgba {
+        let c = match self {
+            Phase::Forces => (0.9, 0.3, 0.3),
+            Phase::Boundaries => (0.9, 0.6, 0.2),
+            Phase::HashBuild => (0.9, 0.9, 0.3),
+            Phase::Narrow => (0.3, 0.9, 0.3),
+            Phase::Constraints => (0.2, 0.6, 0.5),
+            Phase::Integration => (0.3, 0.8, 0.9),
+            Phase::Spawning => (0.5, 0.4, 0.9),
+            Phase::Drawing => (0.9, 0.4, 0.8),
+        };
+        Rgba::new(c.0, c.1, c.2, 1.)
+    }
+}
+
+// Measurements for one frame. Solver phases are summed over substeps.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub phase_ms: [f32; PHASES.len()],
+    // Sum of r^2 v^2 / 2, taking mass as proportional to area and v per substep
+    pub kinetic_energy: f32,
+    pub max_penetration: f32,
+    // Per substep, see `ContactLog::max_speed`
+    pub max_impact_speed: f32,
+    pub contact_count: usize,
+}
+
+impl FrameStats {
+    pub fn add(&mut self, phase: Phase, since: Instant) {
+        self.phase_ms[phase as usize] += since.elapsed().as_secs_f32() * 1000.;
+    }
+
+    pub fn ms(&self, phase: Phase) -> f32 {
+        self.phase_ms[phase as usize]
+    }
+
+    pub fn total_ms(&self) -> f32 {
+        self.phase_ms.iter().sum()
+    }
+
+    pub fn record_contacts(&mut self, log: &ContactLog) {
+        self.contact_count = log.len();
+        self.max_penetration = log.max_depth();
+        self.max_impact_speed = log.max_speed();
+    }
+}
+
+// Rolling window of frame stats, drawn as text and scrolling graphs in screen space
+pub struct StatsHistory {
+    pub samples: VecDeque<FrameStats>,
+    pub capacity: usize,
+}
+
+impl StatsHistory {
+    pub fn new(capacity: usize) -> Self {
+        StatsHistory {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, stats: FrameStats) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(stats);
+    }
+
+    // Lays the panel out downwards from the top left corner of `rect`
+    pub fn draw(&self, draw: &Draw, rect: Rect) {
+        let last = match self.samples.back() {
+            Some(last) => *last,
+            None => return,
+        };
+        let white = Rgba::new(1., 1., 1., 1.);
+        let line_height = 16.;
+        let mut y = rect.top();
+        let text = |s: String, color: Rgba, y: f32| {
+            draw.text(&s)
+                .font_size(13)
+                .left_justify()
+                .w_h(rect.w(), line_height)
+                .x_y(rect.x(), y - line_height / 2.)
+                .color(color);
+        };
+        for phase in PHASES.iter() {
+            text(
+                format!("{:<13}{:>7.2} ms", phase.name(), last.ms(*phase)),
+                phase.color(),
+                y,
+            );
+            y -= line_height;
+        }
+        text(
+            format!("{:<13}{:>7.2} ms", "total", last.total_ms()),
+            white,
+            y,
+        );
+        y -= line_height;
+        text(
+            format!("kinetic      {:>10.2}", last.kinetic_energy),
+            white,
+            y,
+        );
+        y -= line_height;
+        text(
+            format!("penetration  {:>10.3}", last.max_penetration),
+            white,
+            y,
+        );
+        y -= line_height;
+        text(
+            format!("impact speed {:>10.3}", last.max_impact_speed),
+            white,
+            y,
+        );
+        y -= line_height;
+        text(format!("contacts     {:>10}", last.contact_count), white, y);
+        y -= line_height + 8.;
+
+        let graph_height = 60.;
+        let graph = |title: &str, series: Vec<(Vec<f32>, Rgba)>, y: f32| {
+            let area = Rect::from_x_y_w_h(rect.x(), y - graph_height / 2., rect.w(), graph_height);
+            self.draw_graph(draw, area, title, &series);
+        };
+        let phase_series = PHASES
+            .iter()
+            .map(|phase| (self.series(|s| s.ms(*phase)), phase.color()))
+            .collect();
+        graph("phase ms", phase_series, y);
+        y -= graph_height + 8.;
+        graph(
+            "kinetic",
+            vec![(self.series(|s| s.kinetic_energy), white)],
+            y,
+        );
+        y -= graph_height + 8.;
+        graph(
+            "penetration",
+            vec![(self.series(|s| s.max_penetration), white)],
+            y,
+        );
+        y -= graph_height + 8.;
+        graph(
+            "contacts",
+            vec![(self.series(|s| s.contact_count as f32), white)],
+            y,
+        );
+    }
+
+    fn series<F: Fn(&FrameStats) -> f32>(&self, value: F) -> Vec<f32> {
+        self.samples.iter().map(value).collect()
+    }
+
+    // Every series shares one vertical scale, from zero to the largest sample
+    fn draw_graph(&self, draw: &Draw, area: Rect, title: &str, series: &[(Vec<f32>, Rgba)]) {
+        draw.rect()
+            .xy(area.xy())
+            .wh(area.wh())
+            .rgba(0., 0., 0., 0.6)
+            .stroke(GRAY)
+            .stroke_weight(1.);
+        let max = series
+            .iter()
+            .flat_map(|(values, _)| values.iter())
+            .fold(0., |a: f32, b| a.max(*b));
+        draw.text(&format!("{} (max {:.2})", title, max))
+            .font_size(11)
+            .left_justify()
+            .w_h(area.w() - 4., 14.)
+            .x_y(area.x(), area.top() - 8.)
+            .color(GRAY);
+        if max <= 0. {
+            return;
+        }
+        let dx = area.w() / (self.capacity.max(2) - 1) as f32;
+        for (values, color) in series.iter() {
+            let points = values.iter().enumerate().map(|(i, v)| {
+                pt2(
+                    area.left() + i as f32 * dx,
+                    area.bottom() + (v / max) * (area.h() - 16.),
+                )
+            });
+            draw.polyline().weight(1.).points(points).color(*color);
+        }
+    }
+}